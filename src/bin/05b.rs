@@ -19,10 +19,10 @@ fn main() {
     let (seed_ranges_flattened, almanac) = load_input();
     let seed_ranges = seed_ranges(&seed_ranges_flattened);
 
+    let seed_to_location = almanac.compose();
     let result = seed_ranges
         .iter()
-        .flat_map(|&i| almanac.get_range(i))
-        .map(|i| i.start)
+        .filter_map(|&i| seed_to_location.min_over(i))
         .min()
         .unwrap();
 
@@ -6,7 +6,7 @@ use aoc2023::day13::{find_reflection_line, load_input};
 fn main() {
     let result = load_input()
         .iter()
-        .map(|img| find_reflection_line(img))
+        .map(|img| find_reflection_line(img).expect("no symmetry in image"))
         .sum::<usize>();
     println!("{result}");
 }
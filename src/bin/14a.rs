@@ -1,12 +1,12 @@
 // Copyright (c) 2023 Mikołaj Kuranowski
 // SPDX-License-Identifier: MIT
 
-use aoc2023::day14::load_input;
+use aoc2023::day14::{load_input, Direction};
 
 fn main() {
     let mut platform = load_input();
-    platform.tilt_north();
+    platform.tilt(Direction::North);
 
-    let result = platform.north_load();
+    let result = platform.load(Direction::North);
     println!("{result}")
 }
@@ -0,0 +1,71 @@
+// Copyright (c) 2023 Mikołaj Kuranowski
+// SPDX-License-Identifier: MIT
+
+//! Verifies [BitPlatform] against [Platform] on randomly generated platforms,
+//! and compares the time necessary to run tilt cycles.
+//! Usage: `cargo run --release --bin 14bench [SIZE] [CYCLES] [SEED]`.
+
+use aoc2023::day14::{BitPlatform, Direction, Platform};
+use aoc2023::rng::XorShift;
+use std::env::args;
+use std::time::Instant;
+
+fn generate_platform(size: usize, seed: u64) -> Platform {
+    let mut rng = XorShift::new(seed);
+    let mut next_tile = || match rng.below(10) {
+        0..=2 => b'O',
+        3 => b'#',
+        _ => b'.',
+    };
+
+    Platform::new(
+        (0..size)
+            .map(|_| (0..size).map(|_| next_tile()).collect())
+            .collect(),
+    )
+}
+
+fn check_tilt(p: &Platform, dir: Direction) {
+    let mut expected = p.clone();
+    expected.tilt(dir);
+    let mut got = BitPlatform::from(p);
+    got.tilt(dir);
+    assert_eq!(expected, Platform::from(&got));
+    assert_eq!(expected.load(dir), got.load(dir));
+}
+
+fn main() {
+    let mut args = args().skip(1);
+    let size = args.next().map_or(100, |x| x.parse().unwrap());
+    let cycles = args.next().map_or(1000, |x| x.parse().unwrap());
+    let seed = args.next().map_or(0x2023_1214, |x| x.parse().unwrap());
+    let platform = generate_platform(size, seed);
+
+    assert_eq!(platform, Platform::from(&BitPlatform::from(&platform)));
+    for dir in [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ] {
+        check_tilt(&platform, dir);
+    }
+
+    let start = Instant::now();
+    let mut expected = platform.clone();
+    for _ in 0..cycles {
+        expected.cycle();
+    }
+    let platform_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut got = BitPlatform::from(&platform);
+    for _ in 0..cycles {
+        got.cycle();
+    }
+    let bit_platform_time = start.elapsed();
+
+    assert_eq!(expected, Platform::from(&got));
+    assert_eq!(expected.load(Direction::North), got.load(Direction::North));
+    println!("{size}x{size}, {cycles} cycles: Platform {platform_time:?}, BitPlatform {bit_platform_time:?}");
+}
@@ -1,44 +1,44 @@
 // Copyright (c) 2023 Mikołaj Kuranowski
 // SPDX-License-Identifier: MIT
 
-use aoc2023::day16::{count_energy_tiles, load_input, Map, DIR_DOWN, DIR_LEFT, DIR_RIGHT, DIR_UP};
+use aoc2023::day16::{load_input, BeamGraph, Map, DIR_DOWN, DIR_LEFT, DIR_RIGHT, DIR_UP};
 
-fn top_edge_max(m: &Map) -> usize {
+fn top_edge_max(m: &Map, g: &BeamGraph) -> usize {
     (0..m.columns())
-        .map(|y| count_energy_tiles(m, (0, y, DIR_DOWN)))
+        .map(|y| g.count_energy_tiles((0, y, DIR_DOWN)))
         .max()
         .unwrap()
 }
 
-fn left_edge_max(m: &Map) -> usize {
+fn left_edge_max(m: &Map, g: &BeamGraph) -> usize {
     (0..m.rows())
-        .map(|x| count_energy_tiles(m, (x, 0, DIR_RIGHT)))
+        .map(|x| g.count_energy_tiles((x, 0, DIR_RIGHT)))
         .max()
         .unwrap()
 }
 
-fn bottom_edge_max(m: &Map) -> usize {
+fn bottom_edge_max(m: &Map, g: &BeamGraph) -> usize {
     let x = m.rows() - 1;
     (0..m.columns())
-        .map(|y| count_energy_tiles(m, (x, y, DIR_UP)))
+        .map(|y| g.count_energy_tiles((x, y, DIR_UP)))
         .max()
         .unwrap()
 }
 
-fn right_edge_max(m: &Map) -> usize {
+fn right_edge_max(m: &Map, g: &BeamGraph) -> usize {
     let y = m.columns() - 1;
     (0..m.rows())
-        .map(|x| count_energy_tiles(m, (x, y, DIR_LEFT)))
+        .map(|x| g.count_energy_tiles((x, y, DIR_LEFT)))
         .max()
         .unwrap()
 }
 
-fn all_max(m: &Map) -> usize {
+fn all_max(m: &Map, g: &BeamGraph) -> usize {
     [
-        top_edge_max(m),
-        right_edge_max(m),
-        bottom_edge_max(m),
-        left_edge_max(m),
+        top_edge_max(m, g),
+        right_edge_max(m, g),
+        bottom_edge_max(m, g),
+        left_edge_max(m, g),
     ]
     .iter()
     .copied()
@@ -48,6 +48,7 @@ fn all_max(m: &Map) -> usize {
 
 fn main() {
     let map = load_input();
-    let result = all_max(&map);
+    let graph = BeamGraph::new(&map);
+    let result = all_max(&map, &graph);
     println!("{result}");
 }
@@ -0,0 +1,78 @@
+// Copyright (c) 2023 Mikołaj Kuranowski
+// SPDX-License-Identifier: MIT
+
+//! Verifies [BeamGraph] against [count_energy_tiles] on randomly generated contraptions,
+//! and compares the time necessary to evaluate all entry points.
+//! Usage: `cargo run --release --bin 16bench [SIZE] [SEED]`.
+
+use aoc2023::day16::{
+    count_energy_tiles, BeamGraph, Direction, Map, DIR_DOWN, DIR_LEFT, DIR_RIGHT, DIR_UP,
+};
+use aoc2023::rng::XorShift;
+use std::env::args;
+use std::time::Instant;
+
+fn generate_map(size: usize, seed: u64) -> Map {
+    let mut rng = XorShift::new(seed);
+    let mut next_tile = || match rng.below(20) {
+        0 => b'/',
+        1 => b'\\',
+        2 => b'|',
+        3 => b'-',
+        _ => b'.',
+    };
+
+    Map((0..size)
+        .map(|_| (0..size).map(|_| next_tile()).collect())
+        .collect())
+}
+
+fn edge_entries(m: &Map) -> Vec<(i16, i16, Direction)> {
+    let last_row = m.rows() - 1;
+    let last_column = m.columns() - 1;
+    let mut entries = Vec::default();
+    for y in 0..m.columns() {
+        entries.push((0, y, DIR_DOWN));
+        entries.push((last_row, y, DIR_UP));
+    }
+    for x in 0..m.rows() {
+        entries.push((x, 0, DIR_RIGHT));
+        entries.push((x, last_column, DIR_LEFT));
+    }
+    entries
+}
+
+fn main() {
+    let mut args = args().skip(1);
+    let size = args.next().map_or(110, |x| x.parse().unwrap());
+    let seed = args.next().map_or(0x2023_1216, |x| x.parse().unwrap());
+    let map = generate_map(size, seed);
+    let entries = edge_entries(&map);
+
+    let start = Instant::now();
+    let expected: Vec<usize> = entries
+        .iter()
+        .map(|&entry| count_energy_tiles(&map, entry))
+        .collect();
+    let brute_force_time = start.elapsed();
+
+    let start = Instant::now();
+    let graph = BeamGraph::new(&map);
+    let got: Vec<usize> = entries
+        .iter()
+        .map(|&entry| graph.count_energy_tiles(entry))
+        .collect();
+    let graph_time = start.elapsed();
+
+    for ((entry, expected), got) in entries.iter().zip(&expected).zip(&got) {
+        assert_eq!(
+            expected, got,
+            "different energized tile count for {entry:?}"
+        );
+    }
+
+    println!(
+        "{size}x{size}, {} entries: brute force {brute_force_time:?}, beam graph {graph_time:?}",
+        entries.len()
+    );
+}
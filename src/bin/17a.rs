@@ -1,10 +1,13 @@
 // Copyright (c) 2023 Mikołaj Kuranowski
 // SPDX-License-Identifier: MIT
 
-use aoc2023::day17::{load_input, Search};
+use aoc2023::day17::{load_input, Rules, Search};
 
 fn main() {
     let map = load_input();
-    let result = Search::new(&map, 0, 3).run();
+    let result = Search::new(&map, Rules::part_a(&map))
+        .run()
+        .expect("no route found")
+        .cost;
     println!("{result}");
 }
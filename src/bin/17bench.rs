@@ -0,0 +1,49 @@
+// Copyright (c) 2023 Mikołaj Kuranowski
+// SPDX-License-Identifier: MIT
+
+//! Compares [Search] against [ReferenceSearch] on a randomly generated map.
+//! Usage: `cargo run --release --bin 17bench [SIZE] [SEED]`.
+
+use aoc2023::day17::{Map, ReferenceSearch, Route, Rules, Search};
+use aoc2023::rng::XorShift;
+use std::env::args;
+use std::time::{Duration, Instant};
+
+fn generate_map(size: usize, seed: u64) -> Map {
+    let mut rng = XorShift::new(seed);
+    let mut next_digit = || rng.below(9) as u8 + 1;
+
+    Map::new(
+        (0..size)
+            .map(|_| (0..size).map(|_| next_digit()).collect())
+            .collect(),
+    )
+}
+
+fn timed<F: FnOnce() -> Option<Route>>(f: F) -> (u32, Duration) {
+    let start = Instant::now();
+    let route = f().expect("no route found");
+    (route.cost, start.elapsed())
+}
+
+fn main() {
+    let mut args = args().skip(1);
+    let size = args.next().map_or(200, |x| x.parse().unwrap());
+    let seed = args.next().map_or(0x2023_1217, |x| x.parse().unwrap());
+    let map = generate_map(size, seed);
+
+    for (min_steps, max_steps) in [(0, 3), (4, 10)] {
+        let (reference_cost, reference_time) =
+            timed(|| ReferenceSearch::new(&map, min_steps, max_steps).run());
+        let (cost, time) =
+            timed(|| Search::new(&map, Rules::new(&map, min_steps, max_steps)).run());
+
+        assert_eq!(
+            cost, reference_cost,
+            "searches disagree on the cheapest route"
+        );
+        println!(
+            "{size}x{size} {min_steps}..={max_steps}: cost {cost}, reference {reference_time:?}, bucket queue {time:?}"
+        );
+    }
+}
@@ -0,0 +1,26 @@
+// Copyright (c) 2023 Mikołaj Kuranowski
+// SPDX-License-Identifier: MIT
+
+//! Renders the colored trench outline from the dig plan as a PPM image.
+//! Usage: `cargo run --release --bin 18render [a|b] [MAX_SIZE] < input.txt > trench.ppm`.
+
+use aoc2023::day18::{load_input, Interpretation};
+use std::env::args;
+use std::io::{stdout, BufWriter};
+
+fn main() {
+    let mut args = args().skip(1);
+    let interpretation = match args.next().as_deref() {
+        None | Some("a") => Interpretation::Written,
+        Some("b") => Interpretation::Color,
+        Some(other) => panic!("invalid part: {other:?} (expected a or b)"),
+    };
+    let max_size = args.next().map_or(1000, |x| x.parse().unwrap());
+
+    let image = load_input()
+        .render(interpretation, max_size)
+        .expect("invalid dig plan");
+    image
+        .write_ppm(BufWriter::new(stdout().lock()))
+        .expect("failed to write the image");
+}
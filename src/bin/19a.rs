@@ -7,8 +7,8 @@ fn main() {
     let (system, parts) = load_input();
     let result = parts
         .iter()
-        .filter(|&p| system.is_accepted(p))
-        .map(|&p| p.sum())
-        .sum::<usize>();
+        .filter(|p| system.is_accepted(p))
+        .map(|p| p.sum())
+        .sum::<u128>();
     println!("{result}");
 }
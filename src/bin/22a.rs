@@ -4,8 +4,8 @@
 use aoc2023::day22::load_input;
 
 fn main() {
-    let mut bricks = load_input();
-    bricks.all_down();
-    let result = bricks.safe_to_disintegrate().len();
-    eprintln!("{result}");
+    let mut bricks = load_input().expect("invalid bricks");
+    let supports = bricks.settle();
+    let result = supports.safe_to_disintegrate().len();
+    println!("{result}");
 }
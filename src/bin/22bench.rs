@@ -0,0 +1,63 @@
+// Copyright (c) 2023 Mikołaj Kuranowski
+// SPDX-License-Identifier: MIT
+
+//! Verifies [Bricks::settle] against [ReferenceBricks] and the dominator tree
+//! against [reference_chain_reaction] on a randomly generated snapshot
+//! of falling bricks, and compares the time necessary to compute them.
+//! Usage: `cargo run --release --bin 22bench [BRICKS] [SIZE] [FLOOR] [SEED]`.
+
+use aoc2023::day22::{
+    generate_snapshot, reference_chain_reaction, BrickID, Bricks, ReferenceBricks,
+};
+use std::env::args;
+use std::time::Instant;
+
+fn main() {
+    let mut args = args().skip(1);
+    let count = args.next().map_or(1500, |x| x.parse().unwrap());
+    let size = args.next().map_or(10, |x| x.parse().unwrap());
+    let floor = args.next().map_or(0, |x| x.parse().unwrap());
+    let seed = args.next().map_or(0x2023_1222, |x| x.parse().unwrap());
+    let snapshot = generate_snapshot(count, size, floor, seed);
+
+    let start = Instant::now();
+    let mut reference = ReferenceBricks::parse(&snapshot).with_floor(floor);
+    reference.all_down();
+    let expected = reference.support_graph();
+    let reference_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut bricks = Bricks::parse(&snapshot)
+        .and_then(|b| b.with_floor(floor))
+        .expect("invalid bricks");
+    let got = bricks.settle();
+    let settle_time = start.elapsed();
+
+    assert_eq!(expected, got);
+    println!("{count} bricks: ReferenceBricks {reference_time:?}, Bricks {settle_time:?}");
+
+    let start = Instant::now();
+    let expected: Vec<Vec<BrickID>> = got
+        .id_range()
+        .map(|id| reference_chain_reaction(&got, &[id]))
+        .collect();
+    let naive_time = start.elapsed();
+
+    let start = Instant::now();
+    let dominators = got.dominators();
+    let dominators_time = start.elapsed();
+
+    for id in got.id_range() {
+        assert_eq!(expected[id as usize], dominators.falling(id));
+        assert_eq!(expected[id as usize].len(), dominators.fall_count(id));
+        assert_eq!(expected[id as usize], got.falling_after_removing(&[id]));
+    }
+
+    for window in got.id_range().collect::<Vec<_>>().windows(3).step_by(7) {
+        assert_eq!(
+            reference_chain_reaction(&got, window),
+            got.falling_after_removing(window)
+        );
+    }
+    println!("chain reactions: naive {naive_time:?}, dominators {dominators_time:?}");
+}
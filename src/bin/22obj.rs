@@ -0,0 +1,22 @@
+// Copyright (c) 2023 Mikołaj Kuranowski
+// SPDX-License-Identifier: MIT
+
+//! Settles the bricks and exports them as a Wavefront OBJ file,
+//! printing the most critical brick to stderr.
+//! Usage: `cargo run --release --bin 22obj < input.txt > bricks.obj`.
+
+use aoc2023::day22::load_input;
+use std::io::{stdout, BufWriter};
+
+fn main() {
+    let mut bricks = load_input().expect("invalid bricks");
+    let supports = bricks.settle();
+
+    if let Some((id, count)) = supports.dominators().most_critical() {
+        eprintln!("most critical brick: {id} (brings down {count} other bricks)");
+    }
+
+    bricks
+        .write_obj(BufWriter::new(stdout().lock()))
+        .expect("failed to write the OBJ file");
+}
@@ -88,4 +88,8 @@ impl BigBitset {
     pub fn remove(&mut self, i: u64) {
         self.0.set_bit(i, false);
     }
+
+    pub fn union_with(&mut self, other: &Self) {
+        self.0 |= &other.0;
+    }
 }
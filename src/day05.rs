@@ -1,7 +1,7 @@
 // Copyright (c) 2023 Mikołaj Kuranowski
 // SPDX-License-Identifier: MIT
 
-use std::io::stdin;
+use std::io::{stdin, Read};
 
 #[derive(Debug, Clone, Copy)]
 pub struct CopyableRange {
@@ -57,10 +57,23 @@ impl MapEntry {
     }
 }
 
-#[derive(Debug, Default)]
+/// Map is a piecewise-linear function, made out of non-overlapping entries sorted by their
+/// source ranges. Numbers not covered by any entry are mapped to themselves.
+#[derive(Debug, Default, Clone)]
 pub struct Map(Vec<MapEntry>);
 
 impl Map {
+    pub fn entries(&self) -> &[MapEntry] {
+        &self.0
+    }
+
+    /// Returns the smallest range containing the source ranges of all entries.
+    pub fn bounds(&self) -> Option<CopyableRange> {
+        let first = self.0.first()?;
+        let last = self.0.last()?;
+        Some(CopyableRange { start: first.src.start, end: last.src.end })
+    }
+
     pub fn get(&self, i: i64) -> i64 {
         for entry in &self.0 {
             if let Some(j) = entry.get(i) {
@@ -71,8 +84,118 @@ impl Map {
     }
 
     pub fn get_range<'a>(&'a self, i: CopyableRange) -> impl Iterator<Item = CopyableRange> + 'a {
+        self.pieces(i).map(|piece| piece.src.add(piece.delta))
+    }
+
+    /// Splits the range into pieces mapped by the same delta. Pieces not covered by
+    /// any entry are returned with a delta of zero.
+    pub fn pieces<'a>(&'a self, i: CopyableRange) -> impl Iterator<Item = MapEntry> + 'a {
         MapPartition::new(i, self.0.iter())
     }
+
+    /// Returns the smallest number the range is mapped to,
+    /// by only looking at the starts of the pieces of the range.
+    pub fn min_over(&self, i: CopyableRange) -> Option<i64> {
+        self.pieces(i).map(|piece| piece.src.start + piece.delta).min()
+    }
+
+    /// Creates a map equivalent to first applying `self`, and then `other`.
+    ///
+    /// The pieces of `self` are processed in the order of their images, so that
+    /// the entries of `other` can be matched with them in a single sweep.
+    pub fn then(&self, other: &Map) -> Map {
+        let domain = match (self.bounds(), other.bounds()) {
+            (Some(a), Some(b)) => CopyableRange { start: a.start.min(b.start), end: a.end.max(b.end) },
+            (Some(a), None) => a,
+            (None, Some(b)) => b,
+            (None, None) => return Map::default(),
+        };
+
+        // Outside of the domain both maps are identities
+        let mut firsts: Vec<MapEntry> = self.pieces(domain).collect();
+        firsts.sort_by_key(|first| first.src.start + first.delta);
+
+        // As images are sorted by their starts, entries of `other` ending before
+        // the current image also end before all of the following images
+        let mut composed: Vec<MapEntry> = Vec::default();
+        let mut skipped = 0;
+        for first in firsts {
+            let image = first.src.add(first.delta);
+            while skipped < other.0.len() && other.0[skipped].src.end <= image.start {
+                skipped += 1;
+            }
+
+            for second in MapPartition::new(image, other.0[skipped..].iter()) {
+                if first.delta + second.delta != 0 {
+                    composed.push(MapEntry { src: second.src.add(-first.delta), delta: first.delta + second.delta });
+                }
+            }
+        }
+
+        composed.sort_by_key(|entry| entry.src.start);
+        let mut merged: Vec<MapEntry> = Vec::with_capacity(composed.len());
+        for entry in composed {
+            match merged.last_mut() {
+                Some(last) if last.delta == entry.delta && last.src.end == entry.src.start => {
+                    last.src.end = entry.src.end;
+                }
+                _ => merged.push(entry),
+            }
+        }
+        Map(merged)
+    }
+
+    pub fn inverse(&self) -> InverseMap {
+        let mut entries: Vec<MapEntry> = self.0
+            .iter()
+            .map(|entry| MapEntry { src: entry.src.add(entry.delta), delta: -entry.delta })
+            .collect();
+        entries.sort_by_key(|entry| entry.src.start);
+
+        InverseMap { entries, not_identity: self.0.iter().map(|entry| entry.src).collect() }
+    }
+}
+
+/// InverseMap answers which numbers are mapped onto a given number by a [Map].
+/// As the source map doesn't need to be injective, there may be more than one such number.
+#[derive(Debug, Clone)]
+pub struct InverseMap {
+    entries: Vec<MapEntry>,
+    not_identity: Vec<CopyableRange>,
+}
+
+impl InverseMap {
+    pub fn get(&self, i: i64) -> Vec<i64> {
+        let mut result: Vec<i64> = self.entries.iter().filter_map(|entry| entry.get(i)).collect();
+        if !self.not_identity.iter().any(|r| r.contains(i)) {
+            result.push(i);
+        }
+        result.sort();
+        result
+    }
+
+    pub fn get_range(&self, i: CopyableRange) -> Vec<CopyableRange> {
+        let mut result: Vec<CopyableRange> = self.entries
+            .iter()
+            .filter_map(|entry| entry.src.intersection(i).map(|r| r.add(entry.delta)))
+            .collect();
+
+        let mut start = i.start;
+        for hole in &self.not_identity {
+            if hole.start >= i.end {
+                break;
+            } else if hole.start > start {
+                result.push(CopyableRange { start, end: hole.start });
+            }
+            start = start.max(hole.end);
+        }
+        if start < i.end {
+            result.push(CopyableRange { start, end: i.end });
+        }
+
+        result.sort_by_key(|r| r.start);
+        result
+    }
 }
 
 struct MapPartition<'a, I: Iterator<Item=&'a MapEntry>> {
@@ -95,7 +218,7 @@ impl<'a, I: Iterator<Item=&'a MapEntry>> MapPartition<'a, I> {
 }
 
 impl<'a, I: Iterator<Item=&'a MapEntry>> Iterator for MapPartition<'a, I> {
-    type Item = CopyableRange;
+    type Item = MapEntry;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.start >= self.end {
@@ -105,21 +228,21 @@ impl<'a, I: Iterator<Item=&'a MapEntry>> Iterator for MapPartition<'a, I> {
         if self.entry.is_none() {
             let r = CopyableRange { start: self.start, end: self.end };
             self.start = self.end;
-            return Some(r);
+            return Some(MapEntry { src: r, delta: 0 });
         }
 
         let entry = self.entry.unwrap();
         if self.start < entry.src.start {
             let r = CopyableRange { start: self.start, end: self.end.min(entry.src.start) };
             self.start = entry.src.start;
-            return Some(r);
+            return Some(MapEntry { src: r, delta: 0 });
         }
 
         if self.start < entry.src.end {
-            let r = CopyableRange { start: self.start, end: self.end.min(entry.src.end) }.add(entry.delta);
+            let r = CopyableRange { start: self.start, end: self.end.min(entry.src.end) };
             self.start = entry.src.end;
             self.entry = self.next_entries.next();
-            return Some(r);
+            return Some(MapEntry { src: r, delta: entry.delta });
         }
 
         self.entry = self.next_entries.next();
@@ -128,30 +251,93 @@ impl<'a, I: Iterator<Item=&'a MapEntry>> Iterator for MapPartition<'a, I> {
 }
 
 
+/// Almanac is a chain of maps, where `maps[i]` converts numbers
+/// from `categories[i]` to `categories[i + 1]`.
 #[derive(Debug, Default)]
-pub struct Almanac(Vec<Map>);
+pub struct Almanac {
+    maps: Vec<Map>,
+    categories: Vec<String>,
+}
 
 impl Almanac {
+    pub fn maps(&self) -> &[Map] {
+        &self.maps
+    }
+
+    pub fn categories(&self) -> &[String] {
+        &self.categories
+    }
+
     pub fn get(&self, i: i64) -> i64 {
-        self.0.iter().fold(i, |i, map| map.get(i))
+        get_through(&self.maps, i)
     }
 
     pub fn get_range(&self, i: CopyableRange) -> Vec<CopyableRange> {
-        let mut ranges = vec![i];
-        for map in &self.0 {
-            ranges = ranges.iter().flat_map(|&i| map.get_range(i)).collect();
+        get_range_through(&self.maps, i)
+    }
+
+    /// Converts a number between two categories. Returns None if any of the categories is unknown,
+    /// or if `to` comes before `from` in the chain.
+    pub fn convert(&self, from: &str, to: &str, i: i64) -> Option<i64> {
+        Some(get_through(self.maps_between(from, to)?, i))
+    }
+
+    pub fn convert_range(&self, from: &str, to: &str, i: CopyableRange) -> Option<Vec<CopyableRange>> {
+        Some(get_range_through(self.maps_between(from, to)?, i))
+    }
+
+    /// Returns the maps necessary to convert numbers from one category to another.
+    pub fn maps_between(&self, from: &str, to: &str) -> Option<&[Map]> {
+        let from = self.categories.iter().position(|c| c == from)?;
+        let to = self.categories.iter().position(|c| c == to)?;
+        if from <= to {
+            Some(&self.maps[from..to])
+        } else {
+            None
         }
-        ranges
     }
+
+    /// Collapses all maps of the almanac into a single map.
+    pub fn compose(&self) -> Map {
+        compose_all(&self.maps)
+    }
+
+    pub fn compose_between(&self, from: &str, to: &str) -> Option<Map> {
+        Some(compose_all(self.maps_between(from, to)?))
+    }
+
+    pub fn inverse(&self) -> InverseMap {
+        self.compose().inverse()
+    }
+}
+
+fn get_through(maps: &[Map], i: i64) -> i64 {
+    maps.iter().fold(i, |i, map| map.get(i))
+}
+
+fn get_range_through(maps: &[Map], i: CopyableRange) -> Vec<CopyableRange> {
+    let mut ranges = vec![i];
+    for map in maps {
+        ranges = ranges.iter().flat_map(|&i| map.get_range(i)).collect();
+    }
+    ranges
+}
+
+fn compose_all(maps: &[Map]) -> Map {
+    maps.iter().fold(Map::default(), |composed, map| composed.then(map))
 }
 
 pub fn load_input() -> (Vec<i64>, Almanac) {
+    let mut text = String::default();
+    stdin().read_to_string(&mut text).expect("failed to read from stdin");
+    parse_input(&text)
+}
+
+pub fn parse_input(text: &str) -> (Vec<i64>, Almanac) {
     let mut seeds = Vec::default();
     let mut almanac = Almanac::default();
 
-    for line in stdin().lines() {
-        let line = line.expect("failed to read from stdin");
-
+    for line in text.lines() {
         if line.is_empty() {
             // Ignore empty lines
         } else if line.starts_with("seeds: ") {
@@ -162,8 +348,17 @@ pub fn load_input() -> (Vec<i64>, Almanac) {
                 .split_ascii_whitespace()
                 .map(|i| i64::from_str_radix(i, 10).unwrap())
                 .collect();
-        } else if line.ends_with(" map:") {
-            almanac.0.push(Map::default());
+        } else if let Some(header) = line.strip_suffix(" map:") {
+            let (from, to) = header.split_once("-to-").expect("invalid map header");
+            if let Some(previous) = almanac.categories.last() {
+                if previous != from {
+                    panic!("{header} map doesn't follow the previous map, which converts to {previous}");
+                }
+            } else {
+                almanac.categories.push(from.to_string());
+            }
+            almanac.categories.push(to.to_string());
+            almanac.maps.push(Map::default());
         } else {
             let mut parts = line.split_ascii_whitespace();
 
@@ -171,7 +366,7 @@ pub fn load_input() -> (Vec<i64>, Almanac) {
             let src_start = i64::from_str_radix(parts.next().unwrap(), 10).unwrap();
             let len = i64::from_str_radix(parts.next().unwrap(), 10).unwrap();
 
-            almanac.0.last_mut().unwrap().0.push(MapEntry {
+            almanac.maps.last_mut().unwrap().0.push(MapEntry {
                 src: CopyableRange {
                     start: src_start,
                     end: src_start + len,
@@ -181,9 +376,220 @@ pub fn load_input() -> (Vec<i64>, Almanac) {
         }
     }
 
-    for map in almanac.0.iter_mut() {
+    for (idx, map) in almanac.maps.iter_mut().enumerate() {
         map.0.sort_by_key(|i| i.src.start);
+
+        if let Some(w) = map.0.windows(2).find(|w| w[0].src.end > w[1].src.start) {
+            panic!(
+                "{}-to-{} map has overlapping entries: {:?} and {:?}",
+                almanac.categories[idx],
+                almanac.categories[idx + 1],
+                w[0].src,
+                w[1].src,
+            );
+        }
     }
 
     (seeds, almanac)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::XorShift;
+
+    const SAMPLE: &str = "seeds: 79 14 55 13
+
+seed-to-soil map:
+50 98 2
+52 50 48
+
+soil-to-fertilizer map:
+0 15 37
+37 52 2
+39 0 15
+
+fertilizer-to-water map:
+49 53 8
+0 11 42
+42 0 7
+57 7 4
+
+water-to-light map:
+88 18 7
+18 25 70
+
+light-to-temperature map:
+45 77 23
+81 45 19
+68 64 13
+
+temperature-to-humidity map:
+0 69 1
+1 0 69
+
+humidity-to-location map:
+60 56 37
+56 93 4
+";
+
+    /// Generates an almanac with `count` maps, with entries covering parts of `0..100`,
+    /// shifted by at most 50 in either direction. Numbers outside of `-50..150` are never
+    /// mapped to anything but themselves.
+    fn generate_almanac(count: usize, seed: u64) -> Almanac {
+        let mut rng = XorShift::new(seed);
+        let mut almanac = Almanac { maps: Vec::default(), categories: vec!["0".to_string()] };
+
+        for i in 0..count {
+            let mut entries = Vec::default();
+            let mut start = rng.below(10) as i64;
+            while start < 100 {
+                let end = (start + 1 + rng.below(20) as i64).min(100);
+                if rng.below(3) != 0 {
+                    entries.push(MapEntry {
+                        src: CopyableRange { start, end },
+                        delta: rng.below(101) as i64 - 50,
+                    });
+                }
+                start = end + rng.below(5) as i64;
+            }
+
+            almanac.maps.push(Map(entries));
+            almanac.categories.push((i + 1).to_string());
+        }
+        almanac
+    }
+
+    /// Checks if the ranges cover exactly the provided, sorted and deduplicated numbers.
+    fn assert_covers(ranges: &[CopyableRange], expected: &[i64]) {
+        let mut covered: Vec<i64> = ranges.iter().flat_map(|&r| r.start..r.end).collect();
+        covered.sort();
+        covered.dedup();
+        assert_eq!(covered, expected);
+    }
+
+    #[test]
+    fn compose_sample() {
+        let (seeds, almanac) = parse_input(SAMPLE);
+        let composed = almanac.compose();
+        for i in -10..200 {
+            assert_eq!(composed.get(i), almanac.get(i), "seed {i}");
+        }
+        assert_eq!(seeds.iter().map(|&i| composed.get(i)).min(), Some(35));
+        assert!(composed.entries().windows(2).all(|w| w[0].src.end <= w[1].src.start));
+    }
+
+    #[test]
+    fn compose_generated() {
+        for seed in 1..=30 {
+            let almanac = generate_almanac(1 + seed as usize % 7, seed);
+            let composed = almanac.compose();
+            for i in -60..160 {
+                assert_eq!(composed.get(i), almanac.get(i), "seed {seed}, number {i}");
+            }
+
+            // Entries are sorted, non-overlapping, and adjacent entries can't be merged
+            assert!(composed.entries().windows(2).all(|w| {
+                w[0].src.end < w[1].src.start
+                    || (w[0].src.end == w[1].src.start && w[0].delta != w[1].delta)
+            }));
+            assert!(composed.entries().iter().all(|e| e.delta != 0));
+        }
+    }
+
+    #[test]
+    fn inverse_round_trip() {
+        let mut almanacs = vec![parse_input(SAMPLE).1];
+        almanacs.extend((1..=20).map(|seed| generate_almanac(3, seed)));
+
+        for almanac in almanacs {
+            let composed = almanac.compose();
+            let inverse = almanac.inverse();
+
+            for target in -60..160 {
+                let expected: Vec<i64> = (-200..300).filter(|&i| composed.get(i) == target).collect();
+                assert_eq!(inverse.get(target), expected, "target {target}");
+                for i in expected {
+                    assert_eq!(composed.get(i), target);
+                }
+            }
+
+            for (start, end) in [(-60, 160), (0, 1), (10, 35), (90, 120)] {
+                let expected: Vec<i64> = (-200..300)
+                    .filter(|&i| (start..end).contains(&composed.get(i)))
+                    .collect();
+                assert_covers(&inverse.get_range(CopyableRange { start, end }), &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn min_over() {
+        let (seeds, almanac) = parse_input(SAMPLE);
+        let composed = almanac.compose();
+        let ranges = seeds.chunks(2).map(|c| CopyableRange { start: c[0], end: c[0] + c[1] });
+        assert_eq!(ranges.filter_map(|r| composed.min_over(r)).min(), Some(46));
+        assert_eq!(composed.min_over(CopyableRange { start: 5, end: 5 }), None);
+
+        for seed in 1..=20 {
+            let composed = generate_almanac(4, seed).compose();
+            for (start, end) in [(-60, 160), (0, 1), (10, 35), (90, 120), (45, 46)] {
+                let expected = (start..end).map(|i| composed.get(i)).min();
+                assert_eq!(composed.min_over(CopyableRange { start, end }), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn then_matches_sequential() {
+        for seed in 1..=30 {
+            let almanac = generate_almanac(2, seed);
+            let [a, b] = [&almanac.maps[0], &almanac.maps[1]];
+            let composed = a.then(b);
+            for i in -60..160 {
+                assert_eq!(composed.get(i), b.get(a.get(i)), "seed {seed}, number {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn convert_between_categories() {
+        let (_, almanac) = parse_input(SAMPLE);
+        assert_eq!(almanac.categories().first().map(String::as_str), Some("seed"));
+        assert_eq!(almanac.categories().last().map(String::as_str), Some("location"));
+
+        assert_eq!(almanac.convert("seed", "soil", 79), Some(81));
+        assert_eq!(almanac.convert("seed", "location", 79), Some(82));
+        assert_eq!(almanac.convert("soil", "water", 81), Some(81));
+        assert_eq!(almanac.convert("light", "humidity", 74), Some(78));
+        assert_eq!(almanac.convert("water", "water", 1234), Some(1234));
+        assert_eq!(almanac.convert("soil", "seed", 81), None);
+        assert_eq!(almanac.convert("seed", "bogus", 79), None);
+
+        assert_eq!(almanac.maps_between("seed", "location").map(<[Map]>::len), Some(7));
+        assert_eq!(almanac.maps_between("fertilizer", "light").map(<[Map]>::len), Some(2));
+
+        let range = CopyableRange { start: 79, end: 93 };
+        let converted = almanac.convert_range("seed", "soil", range).unwrap();
+        assert_covers(&converted, &(81..95).collect::<Vec<_>>());
+        assert!(almanac.convert_range("location", "seed", range).is_none());
+
+        let composed = almanac.compose_between("soil", "light").unwrap();
+        for i in 0..120 {
+            assert_eq!(Some(composed.get(i)), almanac.convert("soil", "light", i));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "soil-to-water map doesn't follow the previous map, which converts to fertilizer")]
+    fn broken_chain() {
+        parse_input("seeds: 1\n\nseed-to-fertilizer map:\n1 2 3\n\nsoil-to-water map:\n4 5 6\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "seed-to-soil map has overlapping entries")]
+    fn overlapping_entries() {
+        parse_input("seeds: 1\n\nseed-to-soil map:\n50 98 2\n52 50 49\n");
+    }
+
+}
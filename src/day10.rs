@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: MIT
 
 use std::collections::HashMap;
-use std::io::stdin;
+use std::io::{stdin, Read};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Coords(pub u16, pub u16);
@@ -16,12 +16,23 @@ pub enum Direction {
 }
 
 impl Direction {
-    fn move_(self, from: Coords) -> Coords {
+    pub const ALL: [Self; 4] = [Self::N, Self::E, Self::S, Self::W];
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::N => Self::S,
+            Self::E => Self::W,
+            Self::S => Self::N,
+            Self::W => Self::E,
+        }
+    }
+
+    fn move_(self, from: Coords) -> Option<Coords> {
         match self {
-            Self::N => Coords(from.0 - 1, from.1),
-            Self::E => Coords(from.0, from.1 + 1),
-            Self::S => Coords(from.0 + 1, from.1),
-            Self::W => Coords(from.0, from.1 - 1),
+            Self::N => Some(Coords(from.0.checked_sub(1)?, from.1)),
+            Self::E => Some(Coords(from.0, from.1.checked_add(1)?)),
+            Self::S => Some(Coords(from.0.checked_add(1)?, from.1)),
+            Self::W => Some(Coords(from.0, from.1.checked_sub(1)?)),
         }
     }
 }
@@ -38,6 +49,32 @@ pub enum Pipe {
 }
 
 impl Pipe {
+    /// Returns the directions in which the pipe is open, or None for [Pipe::Unknown].
+    pub fn openings(self) -> Option<[Direction; 2]> {
+        match self {
+            Pipe::Unknown => None,
+            Pipe::NS => Some([Direction::N, Direction::S]),
+            Pipe::EW => Some([Direction::E, Direction::W]),
+            Pipe::NE => Some([Direction::N, Direction::E]),
+            Pipe::NW => Some([Direction::N, Direction::W]),
+            Pipe::SW => Some([Direction::S, Direction::W]),
+            Pipe::SE => Some([Direction::S, Direction::E]),
+        }
+    }
+
+    pub fn from_openings(a: Direction, b: Direction) -> Option<Self> {
+        [Pipe::NS, Pipe::EW, Pipe::NE, Pipe::NW, Pipe::SW, Pipe::SE]
+            .into_iter()
+            .find(|p| {
+                let openings = p.openings().unwrap();
+                openings == [a, b] || openings == [b, a]
+            })
+    }
+
+    pub fn is_open(self, dir: Direction) -> bool {
+        self.openings().is_some_and(|o| o.contains(&dir))
+    }
+
     fn move_(self, from: Direction) -> Option<Direction> {
         match self {
             Pipe::Unknown => Some(from),
@@ -80,33 +117,78 @@ pub struct Map {
 }
 
 impl Map {
-    pub fn move_(&self, at: Coords, dir: Direction) -> Option<(Coords, Direction)> {
-        let next_at = dir.move_(at);
-        let next_pipe = self.tiles[next_at.0 as usize][next_at.1 as usize];
-        next_pipe
-            .map(|pipe| pipe.move_(dir))
-            .flatten()
-            .map(|next_dir| (next_at, next_dir))
+    /// Parses the map, replacing the start tile by the pipe inferred with [Map::infer_start_pipe].
+    pub fn parse(text: &str) -> Self {
+        let tiles: Vec<Vec<Option<Pipe>>> = text
+            .lines()
+            .map(|line| {
+                line.as_bytes()
+                    .iter()
+                    .map(|c| match c {
+                        b'|' => Some(Pipe::NS),
+                        b'-' => Some(Pipe::EW),
+                        b'L' => Some(Pipe::NE),
+                        b'J' => Some(Pipe::NW),
+                        b'7' => Some(Pipe::SW),
+                        b'F' => Some(Pipe::SE),
+                        b'S' => Some(Pipe::Unknown),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut start = Coords(0, 0);
+        for (x, row) in tiles.iter().enumerate() {
+            for (y, pipe) in row.iter().enumerate() {
+                if pipe == &Some(Pipe::Unknown) {
+                    start = Coords(x as u16, y as u16);
+                }
+            }
+        }
+
+        let mut map = Map { tiles, start };
+        map.resolve_start();
+        map
     }
 
-    pub fn loops(&self, mut dir: Direction) -> bool {
-        let mut at = self.start;
+    pub fn at(&self, at: Coords) -> Option<Pipe> {
+        *self.tiles.get(at.0 as usize)?.get(at.1 as usize)?
+    }
 
-        if (at.0 == 0 && dir == Direction::N)
-            || (at.1 == 0 && dir == Direction::W)
-            || (at.0 as usize == self.tiles.len() - 1 && dir == Direction::S)
-            || (at.1 as usize == self.tiles[0].len() - 1 && dir == Direction::E)
-        {
-            return false;
+    /// Returns the neighboring tile in the given direction, or None if it lies outside the map.
+    pub fn neighbor(&self, at: Coords, dir: Direction) -> Option<Coords> {
+        let next_at = dir.move_(at)?;
+        if (next_at.0 as usize) < self.tiles.len() && (next_at.1 as usize) < self.tiles[0].len() {
+            Some(next_at)
+        } else {
+            None
         }
+    }
+
+    pub fn move_(&self, at: Coords, dir: Direction) -> Option<(Coords, Direction)> {
+        let next_at = self.neighbor(at, dir)?;
+        let next_dir = self.at(next_at)?.move_(dir)?;
+        Some((next_at, next_dir))
+    }
+
+    /// Checks if the pipe at `at` has an opening in the `dir` direction,
+    /// with the neighboring pipe having a matching opening.
+    pub fn connects(&self, at: Coords, dir: Direction) -> bool {
+        self.neighbor(at, dir)
+            .and_then(|next_at| self.at(next_at))
+            .is_some_and(|pipe| pipe.is_open(dir.opposite()))
+    }
 
+    pub fn loops(&self, mut dir: Direction) -> bool {
+        let mut at = self.start;
         while let Some((next_at, next_dir)) = self.move_(at, dir) {
             if next_at == self.start {
                 return true;
             }
             (at, dir) = (next_at, next_dir);
         }
-        return false;
+        false
     }
 
     pub fn update_min_distances(&self, mut dir: Direction, distances: &mut HashMap<Coords, u32>) {
@@ -134,17 +216,214 @@ impl Map {
             .for_each(|d| self.update_min_distances(d, distances));
     }
 
-    pub fn path(&self) -> PathIterator<'_> {
-        let dir = [Direction::N, Direction::E, Direction::S, Direction::W]
+    /// Returns the shape of the pipe under the start tile, deduced from the neighboring pipes
+    /// connecting to it. If more than 2 neighbors connect to the start tile, the pair
+    /// of openings through which the loop leaves and re-enters the start tile is picked.
+    /// None is returned if there's no such pair, and the start tile isn't connected
+    /// to exactly 2 neighbors.
+    pub fn infer_start_pipe(&self) -> Option<Pipe> {
+        let connected: Vec<Direction> = Direction::ALL
             .into_iter()
-            .filter(|d| self.loops(*d))
-            .next()
-            .unwrap();
+            .filter(|&dir| self.connects(self.start, dir))
+            .collect();
 
-        PathIterator::new(self, dir)
+        for (i, &a) in connected.iter().enumerate() {
+            for &b in &connected[i + 1..] {
+                if self.return_direction(a) == Some(b.opposite()) {
+                    return Pipe::from_openings(a, b);
+                }
+            }
+        }
+
+        match connected[..] {
+            [a, b] => Pipe::from_openings(a, b),
+            _ => None,
+        }
+    }
+
+    /// Replaces [Pipe::Unknown] under the start tile by the pipe returned by
+    /// [Map::infer_start_pipe], if it can be deduced.
+    pub fn resolve_start(&mut self) {
+        if self.at(self.start) != Some(Pipe::Unknown) {
+            return;
+        }
+        if let Some(pipe) = self.infer_start_pipe() {
+            self.tiles[self.start.0 as usize][self.start.1 as usize] = Some(pipe);
+        }
+    }
+
+    /// Walks along the pipes, leaving the start tile in the `dir` direction, and returns
+    /// the direction of the step which brings the walk back onto the start tile,
+    /// or None if the pipes stop connecting first.
+    fn return_direction(&self, mut dir: Direction) -> Option<Direction> {
+        let mut at = self.start;
+        loop {
+            let (next_at, next_dir) = self.move_(at, dir)?;
+            if next_at == self.start {
+                return Some(dir);
+            }
+            (at, dir) = (next_at, next_dir);
+        }
+    }
+
+    /// Returns the area enclosed by the loop through the start tile, in tiles,
+    /// or None if there's no loop through the start tile.
+    pub fn enclosed_area(&self) -> Option<usize> {
+        let path: Vec<Coords> = self.path()?.collect();
+        Some(enclosed_area(&path))
+    }
+
+    /// Returns all tiles enclosed by the loop through the start tile, in reading order,
+    /// or None if there's no loop through the start tile.
+    pub fn enclosed_tiles(&self) -> Option<Vec<Coords>> {
+        let mut on_loop = vec![vec![false; self.tiles[0].len()]; self.tiles.len()];
+        for at in self.path()? {
+            on_loop[at.0 as usize][at.1 as usize] = true;
+        }
+
+        let mut enclosed = Vec::default();
+        for (x, row) in on_loop.iter().enumerate() {
+            // Scan every row, counting crossings of loop pipes opening to the north
+            let mut inside = false;
+            for (y, &is_loop) in row.iter().enumerate() {
+                if is_loop {
+                    let pipe = self.tiles[x][y].unwrap();
+                    if pipe.is_open(Direction::N) {
+                        inside = !inside;
+                    }
+                } else if inside {
+                    enclosed.push(Coords(x as u16, y as u16));
+                }
+            }
+        }
+        Some(enclosed)
+    }
+
+    /// Returns an iterator over the loop through the start tile,
+    /// or None if there's no loop through the start tile.
+    pub fn path(&self) -> Option<PathIterator<'_>> {
+        // Prefer the openings of the start pipe, in case there are other loops through it
+        let start_openings = self.at(self.start).and_then(Pipe::openings);
+        let dir = Direction::ALL
+            .into_iter()
+            .filter(|d| start_openings.is_none_or(|o| o.contains(d)))
+            .find(|&d| self.loops(d))?;
+        Some(PathIterator::new(self, dir))
+    }
+
+    /// Finds all closed loops of pipes on the map, not only the one through the start tile.
+    /// Loops are returned in the reading order of their first tiles.
+    ///
+    /// Every chain of connected pipes is walked only once, so this runs in linear time.
+    /// The start tile must have been resolved with [Map::resolve_start] (as done by
+    /// [Map::parse]) for the loop through it to be found.
+    pub fn closed_loops(&self) -> Vec<PipeLoop> {
+        let mut visited = vec![vec![false; self.tiles[0].len()]; self.tiles.len()];
+        let mut loops = Vec::default();
+
+        for (x, row) in self.tiles.iter().enumerate() {
+            for (y, pipe) in row.iter().enumerate() {
+                let Some([dir, other_dir]) = pipe.and_then(|p| p.openings()) else {
+                    continue;
+                };
+                if visited[x][y] {
+                    continue;
+                }
+
+                let first = Coords(x as u16, y as u16);
+                let (tiles, closed) = self.follow(first, dir);
+                tiles
+                    .iter()
+                    .for_each(|at| visited[at.0 as usize][at.1 as usize] = true);
+
+                if closed {
+                    loops.push(PipeLoop { tiles });
+                } else {
+                    // The chain isn't closed - mark its other half as visited, too
+                    let (tiles, _) = self.follow(first, other_dir);
+                    tiles
+                        .iter()
+                        .for_each(|at| visited[at.0 as usize][at.1 as usize] = true);
+                }
+            }
+        }
+
+        loops
+    }
+
+    /// Walks along the pipes from `first`, leaving it in the `dir` direction, until
+    /// the walk comes back to `first` or the pipes stop connecting. Returns the visited tiles,
+    /// and a flag indicating whether the walk has returned to `first`.
+    fn follow(&self, first: Coords, mut dir: Direction) -> (Vec<Coords>, bool) {
+        let mut tiles = vec![first];
+        let mut at = first;
+        while self.connects(at, dir) {
+            let (next_at, next_dir) = match self.move_(at, dir) {
+                Some(next) => next,
+                None => return (tiles, false),
+            };
+            if next_at == first {
+                return (tiles, true);
+            }
+            tiles.push(next_at);
+            (at, dir) = (next_at, next_dir);
+        }
+        (tiles, false)
+    }
+
+    /// Returns all pipe openings which don't lead to a matching opening of a neighboring pipe
+    /// (or lead outside of the map), in reading order.
+    pub fn dangling_ends(&self) -> Vec<(Coords, Direction)> {
+        let mut ends = Vec::default();
+        for (x, row) in self.tiles.iter().enumerate() {
+            for (y, pipe) in row.iter().enumerate() {
+                let at = Coords(x as u16, y as u16);
+                for dir in pipe.and_then(|p| p.openings()).into_iter().flatten() {
+                    if !self.connects(at, dir) {
+                        ends.push((at, dir));
+                    }
+                }
+            }
+        }
+        ends
+    }
+}
+
+/// PipeLoop is a closed loop of pipes, with tiles listed in the order of traversal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipeLoop {
+    pub tiles: Vec<Coords>,
+}
+
+impl PipeLoop {
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    pub fn enclosed_area(&self) -> usize {
+        enclosed_area(&self.tiles)
     }
 }
 
+/// Returns the number of tiles enclosed by a loop, using the shoelace formula
+/// and Pick's theorem.
+fn enclosed_area(path: &[Coords]) -> usize {
+    // https://en.wikipedia.org/wiki/Shoelace_formula
+    let doubled_area = path
+        .iter()
+        .zip(path.iter().cycle().skip(1))
+        .map(|(a, b)| a.0 as i64 * b.1 as i64 - b.0 as i64 * a.1 as i64)
+        .sum::<i64>()
+        .unsigned_abs() as usize;
+
+    // https://en.wikipedia.org/wiki/Pick%27s_theorem: A = i + b/2 - 1
+    (doubled_area + 2 - path.len()) / 2
+}
+
 pub struct PathIterator<'a> {
     map: &'a Map,
     at: Coords,
@@ -186,34 +465,144 @@ impl<'a> Iterator for PathIterator<'a> {
 }
 
 pub fn load_input() -> Map {
-    let tiles: Vec<Vec<Option<Pipe>>> = stdin()
-        .lines()
-        .map(|line| {
-            line.expect("failed to read from stdin")
-                .as_bytes()
-                .iter()
-                .map(|c| match c {
-                    b'|' => Some(Pipe::NS),
-                    b'-' => Some(Pipe::EW),
-                    b'L' => Some(Pipe::NE),
-                    b'J' => Some(Pipe::NW),
-                    b'7' => Some(Pipe::SW),
-                    b'F' => Some(Pipe::SE),
-                    b'S' => Some(Pipe::Unknown),
-                    _ => None,
-                })
-                .collect()
-        })
-        .collect();
-
-    let mut start = Coords(0, 0);
-    for (x, row) in tiles.iter().enumerate() {
-        for (y, pipe) in row.iter().enumerate() {
-            if pipe == &Some(Pipe::Unknown) {
-                start = Coords(x as u16, y as u16);
-            }
-        }
+    let mut text = String::default();
+    stdin()
+        .read_to_string(&mut text)
+        .expect("failed to read from stdin");
+    Map::parse(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "..F7.
+.FJ|.
+SJ.L7
+|F--J
+LJ...
+";
+
+    const SAMPLE_ENCLOSED: &str = "...........
+.S-------7.
+.|F-----7|.
+.||.....||.
+.||.....||.
+.|L-7.F-J|.
+.|..|.|..|.
+.L--J.L--J.
+...........
+";
+
+    const LARGER_SAMPLE: &str = "FF7FSF7F7F7F7F7F---7
+L|LJ||||||||||||F--J
+FL-7LJLJ||||||LJL-77
+F--JF--7||LJLJ7F7FJ-
+L---JF-JLJ.||-FJLJJ7
+|F|F-JF---7F7-L7L|7|
+|FFJF7L7F-JF7|JL---7
+7-L-JL7||F7|L7F-7F7|
+L.L7LFJ|||||FJL7||LJ
+L7JLJL-JLJLJL--JLJ.L
+";
+
+    /// The start tile connects to 3 neighbors, only 2 of which are on the loop.
+    const THREE_NEIGHBORS: &str = ".....
+.F-7.
+.|.|.
+.L-S-
+.....
+";
+
+    #[test]
+    fn infer_start_pipe() {
+        let map = Map::parse(SAMPLE);
+        assert_eq!(map.start, Coords(2, 0));
+        assert_eq!(map.at(map.start), Some(Pipe::SE));
+
+        assert_eq!(Map::parse(SAMPLE_ENCLOSED).at(Coords(1, 1)), Some(Pipe::SE));
+        assert_eq!(Map::parse(LARGER_SAMPLE).at(Coords(0, 4)), Some(Pipe::SW));
+
+        let map = Map::parse(THREE_NEIGHBORS);
+        assert_eq!(map.at(map.start), Some(Pipe::NW));
+    }
+
+    #[test]
+    fn infer_start_pipe_without_loop() {
+        let map = Map::parse("-S-\n");
+        assert_eq!(map.at(map.start), Some(Pipe::EW));
+        assert!(map.path().is_none());
+
+        let map = Map::parse(".|.\n-S-\n.|.\n");
+        assert_eq!(map.at(map.start), Some(Pipe::Unknown));
+    }
+
+    #[test]
+    fn enclosed_tiles() {
+        let map = Map::parse(SAMPLE);
+        assert_eq!(map.enclosed_tiles(), Some(vec![Coords(2, 2)]));
+        assert_eq!(map.enclosed_area(), Some(1));
+
+        let map = Map::parse(SAMPLE_ENCLOSED);
+        assert_eq!(
+            map.enclosed_tiles(),
+            Some(vec![Coords(6, 2), Coords(6, 3), Coords(6, 7), Coords(6, 8)])
+        );
+        assert_eq!(map.enclosed_area(), Some(4));
+
+        let map = Map::parse(LARGER_SAMPLE);
+        assert_eq!(map.enclosed_tiles().unwrap().len(), 10);
+        assert_eq!(map.enclosed_area(), Some(10));
+
+        let map = Map::parse(THREE_NEIGHBORS);
+        assert_eq!(map.enclosed_tiles(), Some(vec![Coords(2, 2)]));
+        assert_eq!(map.enclosed_area(), Some(1));
+    }
+
+    #[test]
+    fn closed_loops() {
+        let map = Map::parse(THREE_NEIGHBORS);
+        let loops = map.closed_loops();
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 8);
+        assert!(loops[0].tiles.contains(&map.start));
+        assert_eq!(loops[0].enclosed_area(), 1);
+
+        // Two separate loops, one of them touching the map edges, and a dangling chain
+        let map = Map::parse("F7.F-7\nLJ.|.|\n-7.L-J\n.|.S..\n");
+        let loops = map.closed_loops();
+        assert_eq!(loops.len(), 2);
+        assert_eq!(loops[0].tiles.first(), Some(&Coords(0, 0)));
+        assert_eq!(loops[0].len(), 4);
+        assert_eq!(loops[0].enclosed_area(), 0);
+        assert_eq!(loops[1].tiles.first(), Some(&Coords(0, 3)));
+        assert_eq!(loops[1].len(), 8);
+        assert_eq!(loops[1].enclosed_area(), 1);
     }
 
-    Map { tiles, start }
+    #[test]
+    fn closed_loops_long_chains() {
+        let vertical = "|".repeat(40) + "\n";
+        let map = Map::parse(&vertical.repeat(4000));
+        assert!(map.closed_loops().is_empty());
+
+        let horizontal = "-".repeat(4000) + "\n";
+        let map = Map::parse(&horizontal.repeat(40));
+        assert!(map.closed_loops().is_empty());
+    }
+
+    #[test]
+    fn dangling_ends() {
+        // Only the pipe east of the start tile dangles, none of the loop pipes do
+        assert_eq!(
+            Map::parse(THREE_NEIGHBORS).dangling_ends(),
+            vec![(Coords(3, 4), Direction::E), (Coords(3, 4), Direction::W)]
+        );
+
+        let map = Map::parse("F7.F-7\nLJ.|.|\n-7.L-J\n.|.S..\n");
+        assert_eq!(
+            map.dangling_ends(),
+            vec![(Coords(2, 0), Direction::W), (Coords(3, 1), Direction::S),]
+        );
+    }
 }
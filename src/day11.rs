@@ -1,9 +1,10 @@
 // Copyright (c) 2023 Mikołaj Kuranowski
 // SPDX-License-Identifier: MIT
 
+use std::collections::BTreeSet;
 use std::io::stdin;
 
-pub type Coords = [i32; 2];
+pub type Coords = [i64; 2];
 
 pub fn load_input() -> Vec<Coords> {
     let mut galaxies = Vec::default();
@@ -12,7 +13,7 @@ pub fn load_input() -> Vec<Coords> {
         let line = line.expect("failed to read from stdin");
         for (col, &c) in line.as_bytes().iter().enumerate() {
             if c == b'#' {
-                galaxies.push([row as i32, col as i32]);
+                galaxies.push([row as i64, col as i64]);
             }
         }
     }
@@ -20,40 +21,582 @@ pub fn load_input() -> Vec<Coords> {
     galaxies
 }
 
-fn expand_axis(coords: &mut [Coords], axis: usize, factor: i32) {
-    let mut last = coords.iter().map(|c| c[axis]).max().unwrap();
-    let mut curr: i32 = 0;
+/// Returns the sorted, distinct values of the galaxy coordinates on the provided axis,
+/// together with the number of galaxies on every such line.
+fn occupied_lines(coords: &[Coords], axis: usize) -> Vec<(i64, usize)> {
+    let mut values: Vec<i64> = coords.iter().map(|c| c[axis]).collect();
+    values.sort_unstable();
 
-    while curr < last {
-        if coords.iter().any(|c| c[axis] == curr) {
-            // There's a galaxy on `curr` axis - move to the next one
-            curr += 1;
+    let mut lines: Vec<(i64, usize)> = Vec::default();
+    for value in values {
+        match lines.last_mut() {
+            Some((line, count)) if *line == value => *count += 1,
+            _ => lines.push((value, 1)),
+        }
+    }
+    lines
+}
+
+/// Describes how many lines every row or column of the universe becomes after the expansion.
+///
+/// The expanded coordinate of a line is the sum of widths of all lines before it,
+/// counting from 0 (or from the first galaxy, if it has a negative coordinate).
+///
+/// Create it with [Expansion::uniform] or [Expansion::weighted].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    /// Widths of the consecutive empty lines, repeated if there are more empty lines than widths.
+    /// Never empty.
+    empty_widths: Vec<i64>,
+
+    /// Width of lines with at least one galaxy, but fewer than `dense_threshold`.
+    occupied_width: i64,
+
+    /// Lines with at least this many galaxies are considered dense.
+    dense_threshold: usize,
+
+    /// Width of dense lines. Setting this to 0 contracts dense regions,
+    /// placing galaxies from consecutive dense lines on the same coordinate.
+    dense_width: i64,
+}
+
+impl Expansion {
+    /// Every empty line is replaced by `factor + 1` lines.
+    pub fn uniform(factor: i64) -> Self {
+        Self::weighted(vec![factor])
+    }
+
+    /// The k-th empty line is replaced by `factors[k % factors.len()] + 1` lines.
+    ///
+    /// Panics if `factors` is empty.
+    pub fn weighted(factors: Vec<i64>) -> Self {
+        assert!(
+            !factors.is_empty(),
+            "at least one expansion factor is required"
+        );
+        Self {
+            empty_widths: factors.into_iter().map(|f| f + 1).collect(),
+            occupied_width: 1,
+            dense_threshold: usize::MAX,
+            dense_width: 1,
+        }
+    }
+
+    /// Replaces lines with at least `threshold` galaxies by `width` lines.
+    /// A `width` of 0 contracts dense regions, placing galaxies from consecutive dense lines
+    /// on the same coordinate.
+    pub fn with_dense_lines(mut self, threshold: usize, width: i64) -> Self {
+        self.dense_threshold = threshold;
+        self.dense_width = width;
+        self
+    }
+
+    fn occupied_line_width(&self, galaxies: usize) -> i64 {
+        if galaxies >= self.dense_threshold {
+            self.dense_width
         } else {
-            // No galaxies on `curr` axis - expand it
-            coords
-                .iter_mut()
-                .filter(|c| c[axis] > curr)
-                .for_each(|c| c[axis] += factor);
-            curr += factor + 1;
-            last += factor;
+            self.occupied_width
+        }
+    }
+
+    /// Returns the total width of `len` consecutive empty lines, starting with the `first`-th one.
+    /// Runs in constant time thanks to the `prefix` sums of `empty_widths`.
+    fn empty_run_width(&self, prefix: &[i64], first: i64, len: i64) -> i64 {
+        let cycle = self.empty_widths.len() as i64;
+        let widths_before =
+            |n: i64| (n / cycle) * prefix[cycle as usize] + prefix[(n % cycle) as usize];
+        widths_before(first + len) - widths_before(first)
+    }
+}
+
+fn expand_axis(coords: &mut [Coords], axis: usize, rule: &Expansion) {
+    let occupied = occupied_lines(coords, axis);
+    let Some(&(first, _)) = occupied.first() else {
+        return;
+    };
+
+    let mut prefix = vec![0; rule.empty_widths.len() + 1];
+    for (i, &width) in rule.empty_widths.iter().enumerate() {
+        prefix[i + 1] = prefix[i] + width;
+    }
+
+    // expanded[i] is the new coordinate of the occupied[i] line
+    let mut expanded = Vec::with_capacity(occupied.len());
+    let mut position = first.min(0);
+    let mut next_line = position;
+    let mut empty_seen = 0;
+    for &(line, galaxies) in &occupied {
+        let empty = line - next_line;
+        position += rule.empty_run_width(&prefix, empty_seen, empty);
+        empty_seen += empty;
+
+        expanded.push(position);
+        position += rule.occupied_line_width(galaxies);
+        next_line = line + 1;
+    }
+
+    for c in coords {
+        let i = occupied
+            .binary_search_by_key(&c[axis], |&(line, _)| line)
+            .unwrap();
+        c[axis] = expanded[i];
+    }
+}
+
+/// Expands the universe according to separate rules for the rows and for the columns.
+pub fn expand_with(coords: &mut [Coords], rows: &Expansion, cols: &Expansion) {
+    expand_axis(coords, 0, rows);
+    expand_axis(coords, 1, cols);
+}
+
+pub fn expand(coords: &mut [Coords], factor: i64) {
+    let rule = Expansion::uniform(factor);
+    expand_with(coords, &rule, &rule);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Metric {
+    /// |dx| + |dy|
+    Manhattan,
+
+    /// max(|dx|, |dy|)
+    Chebyshev,
+
+    /// dx² + dy²
+    EuclideanSquared,
+}
+
+impl Metric {
+    pub fn distance(self, a: Coords, b: Coords) -> u128 {
+        let dx = a[0].abs_diff(b[0]) as u128;
+        let dy = a[1].abs_diff(b[1]) as u128;
+        match self {
+            Metric::Manhattan => dx + dy,
+            Metric::Chebyshev => dx.max(dy),
+            Metric::EuclideanSquared => dx * dx + dy * dy,
+        }
+    }
+
+    /// Returns the largest difference on a single axis between two points
+    /// not farther apart than `distance`.
+    fn axis_bound(self, distance: u128) -> i64 {
+        let bound = match self {
+            Metric::Manhattan | Metric::Chebyshev => distance,
+            Metric::EuclideanSquared => distance.isqrt(),
+        };
+        bound.min(i64::MAX as u128) as i64
+    }
+}
+
+/// Returns the sum of differences between all pairs of values,
+/// computed in O(n log n) by sorting the values.
+fn sum_differences(mut values: Vec<i128>) -> u128 {
+    values.sort_unstable();
+
+    // After sorting, values[i] is greater than or equal to all i preceding values,
+    // so it contributes i * values[i] - sum(values[..i]) to the total.
+    let mut sum: i128 = 0;
+    let mut prefix_sum: i128 = 0;
+    for (i, &value) in values.iter().enumerate() {
+        sum += i as i128 * value - prefix_sum;
+        prefix_sum += value;
+    }
+    sum as u128
+}
+
+/// Returns the sum of squared differences between all pairs of values, computed in O(n)
+/// using the identity: sum over pairs of (a - b)² = n * sum(a²) - (sum a)².
+///
+/// Panics if any of the intermediate sums doesn't fit in an [u128].
+fn sum_squared_differences(values: impl Iterator<Item = i64>) -> u128 {
+    const OVERFLOW: &str = "sum of squared distances doesn't fit in an u128";
+
+    let mut n: u128 = 0;
+    let mut sum: i128 = 0;
+    let mut sum_of_squares: u128 = 0;
+    for value in values {
+        let square = (value as i128 * value as i128) as u128;
+        n += 1;
+        sum = sum.checked_add(value as i128).expect(OVERFLOW);
+        sum_of_squares = sum_of_squares.checked_add(square).expect(OVERFLOW);
+    }
+
+    let square_of_sum = sum
+        .unsigned_abs()
+        .checked_mul(sum.unsigned_abs())
+        .expect(OVERFLOW);
+    n.checked_mul(sum_of_squares).expect(OVERFLOW) - square_of_sum
+}
+
+/// Returns the sum of Manhattan distances between all pairs of galaxies.
+pub fn sum_distances(galaxies: &[Coords]) -> u128 {
+    sum_distances_by(galaxies, Metric::Manhattan)
+}
+
+/// Returns the sum of distances between all pairs of galaxies.
+///
+/// Panics if the sum of squared Euclidean distances doesn't fit in an [u128].
+pub fn sum_distances_by(galaxies: &[Coords], metric: Metric) -> u128 {
+    let axis = |i: usize| galaxies.iter().map(move |c| c[i]);
+    match metric {
+        Metric::Manhattan => (0..2)
+            .map(|i| sum_differences(axis(i).map(|x| x as i128).collect()))
+            .sum(),
+
+        // Rotating by 45° turns Chebyshev distance into half of the Manhattan distance:
+        // max(|dx|, |dy|) = (|dx + dy| + |dx - dy|) / 2
+        Metric::Chebyshev => {
+            let u = galaxies.iter().map(|c| c[0] as i128 + c[1] as i128);
+            let v = galaxies.iter().map(|c| c[0] as i128 - c[1] as i128);
+            (sum_differences(u.collect()) + sum_differences(v.collect())) / 2
+        }
+
+        Metric::EuclideanSquared => (0..2).map(|i| sum_squared_differences(axis(i))).sum(),
+    }
+}
+
+/// Returns the indices `(a, b)` (with `a < b`) of the closest pair of galaxies
+/// and the distance between them, or [None] if there are fewer than 2 galaxies.
+/// If several pairs are equally close, the lexicographically smallest `(a, b)` is returned.
+///
+/// Uses a sweep line over galaxies sorted by the first coordinate, keeping
+/// only the galaxies within the current best distance in an ordered set.
+pub fn closest_pair(galaxies: &[Coords], metric: Metric) -> Option<(usize, usize, u128)> {
+    let mut order: Vec<usize> = (0..galaxies.len()).collect();
+    order.sort_unstable_by_key(|&i| galaxies[i]);
+
+    let mut best: Option<(usize, usize, u128)> = None;
+    let mut active: BTreeSet<(i64, usize)> = BTreeSet::default();
+    let mut oldest = 0;
+
+    for (k, &i) in order.iter().enumerate() {
+        let [x, y] = galaxies[i];
+
+        // Only galaxies not farther than the best distance along both axes are checked,
+        // so that all pairs at exactly the best distance are considered for the tie-break.
+        let bound = best.map_or(i64::MAX, |(_, _, distance)| metric.axis_bound(distance));
+
+        // Forget galaxies too far away along the first axis
+        while oldest < k && x.abs_diff(galaxies[order[oldest]][0]) > bound as u64 {
+            let j = order[oldest];
+            active.remove(&(galaxies[j][1], j));
+            oldest += 1;
+        }
+
+        // Only check galaxies close enough along the second axis
+        let low = (y.saturating_sub(bound), 0);
+        let high = (y.saturating_add(bound), usize::MAX);
+        for &(_, j) in active.range(low..=high) {
+            let candidate = (
+                j.min(i),
+                j.max(i),
+                metric.distance(galaxies[i], galaxies[j]),
+            );
+            if best.is_none_or(|best| {
+                (candidate.2, candidate.0, candidate.1) < (best.2, best.0, best.1)
+            }) {
+                best = Some(candidate);
+            }
+        }
+
+        active.insert((y, i));
+    }
+
+    best
+}
+
+/// Returns the indices `(a, b)` (with `a < b`) of the farthest pair of galaxies
+/// and the distance between them, or [None] if there are fewer than 2 galaxies.
+/// If several pairs are equally far apart, the lexicographically smallest `(a, b)` is returned.
+pub fn farthest_pair(galaxies: &[Coords], metric: Metric) -> Option<(usize, usize, u128)> {
+    if galaxies.len() < 2 {
+        return None;
+    }
+
+    let candidates: Vec<(usize, usize)> = match metric {
+        // Manhattan distance is max(|du|, |dv|) in coordinates rotated by 45°,
+        // (u, v) = (x + y, x - y), and Chebyshev distance is max(|dx|, |dy|).
+        // The farthest pairs are thus formed by the extreme galaxies along one of the axes.
+        Metric::Manhattan => extreme_pairs(
+            galaxies,
+            &[&|c: Coords| c[0] as i128 + c[1] as i128, &|c: Coords| {
+                c[0] as i128 - c[1] as i128
+            }],
+        ),
+        Metric::Chebyshev => extreme_pairs(
+            galaxies,
+            &[&|c: Coords| c[0] as i128, &|c: Coords| c[1] as i128],
+        ),
+        Metric::EuclideanSquared => antipodal_pairs(galaxies),
+    };
+
+    candidates
+        .into_iter()
+        .map(|(a, b)| (a, b, metric.distance(galaxies[a], galaxies[b])))
+        .min_by_key(|&(a, b, distance)| (std::cmp::Reverse(distance), a, b))
+}
+
+/// Returns candidates for the farthest pair of galaxies, for a distance equal to
+/// the maximum of absolute differences of the provided keys: for every key, the pair of
+/// galaxies with the lowest index among those with the minimum and the maximum key.
+fn extreme_pairs(galaxies: &[Coords], keys: &[&dyn Fn(Coords) -> i128]) -> Vec<(usize, usize)> {
+    keys.iter()
+        .map(|key| {
+            // min_by_key returns the first minimum, while max_by_key returns the last maximum
+            let min = (0..galaxies.len())
+                .min_by_key(|&i| key(galaxies[i]))
+                .unwrap();
+            let max = (0..galaxies.len())
+                .rev()
+                .max_by_key(|&i| key(galaxies[i]))
+                .unwrap();
+            if min == max {
+                // All keys are equal - any pair is as good as any other
+                (0, 1)
+            } else {
+                (min.min(max), min.max(max))
+            }
+        })
+        .collect()
+}
+
+fn cross(o: Coords, a: Coords, b: Coords) -> i128 {
+    (a[0] - o[0]) as i128 * (b[1] - o[1]) as i128 - (a[1] - o[1]) as i128 * (b[0] - o[0]) as i128
+}
+
+/// Returns indices of the galaxies on the convex hull, in counter-clockwise order,
+/// using Andrew's monotone chain algorithm. Collinear points are skipped, and if several
+/// galaxies are in the same place, only the one with the lowest index is considered.
+fn convex_hull(galaxies: &[Coords]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..galaxies.len()).collect();
+    order.sort_unstable_by_key(|&i| (galaxies[i], i));
+    order.dedup_by_key(|i| galaxies[*i]);
+    if order.len() < 3 {
+        return order;
+    }
+
+    let mut hull: Vec<usize> = Vec::with_capacity(2 * order.len());
+    for pass in [order.clone(), order.iter().rev().copied().collect()] {
+        let start = hull.len();
+        for i in pass {
+            while hull.len() >= start + 2
+                && cross(
+                    galaxies[hull[hull.len() - 2]],
+                    galaxies[hull[hull.len() - 1]],
+                    galaxies[i],
+                ) <= 0
+            {
+                hull.pop();
+            }
+            hull.push(i);
         }
+        hull.pop(); // The last point is the first point of the next chain
     }
+    hull
 }
 
-pub fn expand(coords: &mut [Coords], factor: i32) {
-    expand_axis(coords, 0, factor);
-    expand_axis(coords, 1, factor);
+/// Returns pairs of galaxy indices forming all antipodal pairs of the convex hull,
+/// found with rotating calipers; the farthest pairs in the Euclidean sense are among them.
+fn antipodal_pairs(galaxies: &[Coords]) -> Vec<(usize, usize)> {
+    let hull = convex_hull(galaxies);
+    let h = hull.len();
+    let pair = |a: usize, b: usize| {
+        let (a, b) = (hull[a % h], hull[b % h]);
+        (a.min(b), a.max(b))
+    };
+
+    match h {
+        0 => return Vec::default(),
+
+        // All galaxies are in the same place, so any two of them are the farthest pair.
+        // Note that these are galaxy indices (the lowest possible ones), not hull indices.
+        1 => return vec![(0, 1)],
+
+        2 => return vec![pair(0, 1)],
+        _ => {}
+    }
+
+    let point = |i: usize| galaxies[hull[i % h]];
+    let height = |i: usize, j: usize| cross(point(i), point(i + 1), point(j));
+    let mut pairs = Vec::default();
+    let mut j = 1;
+    for i in 0..h {
+        // Advance j while it moves away from the edge (i, i+1)
+        while height(i, j + 1) > height(i, j) {
+            j = (j + 1) % h;
+        }
+        pairs.push(pair(i, j));
+        pairs.push(pair(i + 1, j));
+
+        // If the edge (j, j+1) is parallel to (i, i+1), j+1 is antipodal too
+        if height(i, j + 1) == height(i, j) {
+            pairs.push(pair(i, j + 1));
+            pairs.push(pair(i + 1, j + 1));
+        }
+    }
+    pairs
 }
 
-pub fn sum_distances(galaxies: &[Coords]) -> usize {
-    let mut sum: usize = 0;
-    for i in 0..galaxies.len() {
-        let a = galaxies[i];
-        for j in (i + 1)..galaxies.len() {
-            let b = galaxies[j];
-            let dist = (b[0] - a[0]).abs() + (b[1] - a[1]).abs();
-            sum += dist as usize;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::XorShift;
+
+    const METRICS: [Metric; 3] = [
+        Metric::Manhattan,
+        Metric::Chebyshev,
+        Metric::EuclideanSquared,
+    ];
+
+    const SAMPLE: &str = "...#......
+.......#..
+#.........
+..........
+......#...
+.#........
+.........#
+..........
+.......#..
+#...#.....
+";
+
+    fn parse(text: &str) -> Vec<Coords> {
+        let mut galaxies = Vec::default();
+        for (row, line) in text.lines().enumerate() {
+            for (col, &c) in line.as_bytes().iter().enumerate() {
+                if c == b'#' {
+                    galaxies.push([row as i64, col as i64]);
+                }
+            }
+        }
+        galaxies
+    }
+
+    /// Generates `count` galaxies with coordinates in `-range..range`. Small ranges
+    /// produce many duplicate galaxies; `collinear` places all of them on a single
+    /// diagonal line.
+    fn generate_galaxies(count: usize, range: i64, collinear: bool, seed: u64) -> Vec<Coords> {
+        let mut rng = XorShift::new(seed);
+        let mut next = || rng.below(2 * range as u64) as i64 - range;
+
+        (0..count)
+            .map(|_| {
+                let x = next();
+                if collinear {
+                    [x, 2 * x + 3]
+                } else {
+                    [x, next()]
+                }
+            })
+            .collect()
+    }
+
+    fn all_pairs(galaxies: &[Coords], metric: Metric) -> Vec<(usize, usize, u128)> {
+        let mut pairs = Vec::default();
+        for a in 0..galaxies.len() {
+            for b in a + 1..galaxies.len() {
+                pairs.push((a, b, metric.distance(galaxies[a], galaxies[b])));
+            }
+        }
+        pairs
+    }
+
+    fn check_metrics(galaxies: &[Coords]) {
+        for metric in METRICS {
+            let pairs = all_pairs(galaxies, metric);
+
+            let sum: u128 = pairs.iter().map(|&(_, _, d)| d).sum();
+            assert_eq!(sum_distances_by(galaxies, metric), sum, "{metric:?}");
+
+            let closest = pairs.iter().copied().min_by_key(|&(a, b, d)| (d, a, b));
+            assert_eq!(closest_pair(galaxies, metric), closest, "{metric:?}");
+
+            let farthest = pairs
+                .iter()
+                .copied()
+                .min_by_key(|&(a, b, d)| (std::cmp::Reverse(d), a, b));
+            assert_eq!(farthest_pair(galaxies, metric), farthest, "{metric:?}");
+        }
+    }
+
+    #[test]
+    fn sample() {
+        let mut galaxies = parse(SAMPLE);
+        expand(&mut galaxies, 1);
+        assert_eq!(sum_distances(&galaxies), 374);
+        check_metrics(&galaxies);
+
+        let mut galaxies = parse(SAMPLE);
+        expand(&mut galaxies, 999_999);
+        assert_eq!(sum_distances(&galaxies), 82000210);
+        check_metrics(&galaxies);
+    }
+
+    #[test]
+    fn metrics_random() {
+        for seed in 1..=50 {
+            check_metrics(&generate_galaxies(seed as usize * 3, 1000, false, seed));
+        }
+    }
+
+    #[test]
+    fn metrics_duplicates() {
+        for seed in 1..=50 {
+            check_metrics(&generate_galaxies(seed as usize * 3, 3, false, seed));
+        }
+    }
+
+    #[test]
+    fn metrics_collinear() {
+        for seed in 1..=50 {
+            check_metrics(&generate_galaxies(seed as usize * 3, 50, true, seed));
+            check_metrics(&generate_galaxies(seed as usize * 3, 2, true, seed));
+        }
+    }
+
+    #[test]
+    fn metrics_degenerate() {
+        check_metrics(&[]);
+        check_metrics(&[[1, 1]]);
+        check_metrics(&[[1, 1], [1, 1]]);
+        check_metrics(&[[5, 5], [5, 5], [5, 5], [0, 0], [0, 0]]);
+        check_metrics(&[[0, 0], [0, 4], [3, 0], [3, 4], [0, 0], [3, 4]]);
+    }
+
+    #[test]
+    fn expand_weighted() {
+        let galaxies = generate_galaxies(40, 30, false, 7);
+        let rows = Expansion::weighted(vec![2, 0, 5]).with_dense_lines(3, 0);
+        let cols = Expansion::uniform(4);
+
+        // Naive expansion, summing widths line by line
+        let naive_axis = |axis: usize, widths: &[i64], dense: Option<(usize, i64)>| {
+            let first = galaxies.iter().map(|c| c[axis]).min().unwrap().min(0);
+            let last = galaxies.iter().map(|c| c[axis]).max().unwrap();
+            let mut new_coords = Vec::default();
+            let mut position = first;
+            let mut empty_seen = 0;
+            for line in first..=last {
+                new_coords.push(position);
+                let count = galaxies.iter().filter(|c| c[axis] == line).count();
+                position += match (count, dense) {
+                    (0, _) => {
+                        empty_seen += 1;
+                        widths[(empty_seen - 1) % widths.len()]
+                    }
+                    (n, Some((threshold, width))) if n >= threshold => width,
+                    _ => 1,
+                };
+            }
+            move |line: i64| new_coords[(line - first) as usize]
+        };
+        let new_row = naive_axis(0, &[3, 1, 6], Some((3, 0)));
+        let new_col = naive_axis(1, &[5], None);
+
+        let mut expanded = galaxies.clone();
+        expand_with(&mut expanded, &rows, &cols);
+        for (original, expanded) in galaxies.iter().zip(&expanded) {
+            assert_eq!(*expanded, [new_row(original[0]), new_col(original[1])]);
         }
     }
-    sum
 }
@@ -1,6 +1,8 @@
 // Copyright (c) 2023 Mikołaj Kuranowski
 // SPDX-License-Identifier: MIT
 
+use num::{BigUint, One, Zero};
+use std::collections::HashMap;
 use std::io::stdin;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -29,35 +31,481 @@ impl Condition {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SpringsRow {
     pub conditions: Vec<Condition>,
     pub broken_groups: Vec<u8>,
 }
 
+impl SpringsRow {
+    pub fn parse(line: &str) -> Self {
+        let (condition_str, broken_groups_str) = line.split_once(' ').unwrap();
+
+        let conditions = condition_str
+            .as_bytes()
+            .iter()
+            .copied()
+            .map(Condition::from_input_byte)
+            .collect();
+
+        let broken_groups = broken_groups_str
+            .split(',')
+            .map(|s| s.parse().unwrap())
+            .collect();
+
+        SpringsRow {
+            conditions,
+            broken_groups,
+        }
+    }
+
+    /// Returns the row repeated `factor` times, with conditions joined by [Condition::Unknown].
+    pub fn unfolded(&self, factor: usize) -> Self {
+        let conditions = (0..factor)
+            .flat_map(|i| {
+                let separator = if i > 0 {
+                    Some(Condition::Unknown)
+                } else {
+                    None
+                };
+                separator.into_iter().chain(self.conditions.iter().copied())
+            })
+            .collect();
+
+        Self {
+            conditions,
+            broken_groups: self.broken_groups.repeat(factor),
+        }
+    }
+
+    /// Returns the number of arrangements of operational and damaged springs consistent
+    /// with both the conditions and the broken groups, or [None] if it doesn't fit in an [u128].
+    pub fn count_arrangements(&self) -> Option<u128> {
+        let table = self.arrangements_table(0_u128, 1, |a, b| a.checked_add(*b))?;
+        Some(table[0])
+    }
+
+    /// Returns the number of arrangements of operational and damaged springs consistent
+    /// with both the conditions and the broken groups.
+    pub fn count_arrangements_big(&self) -> BigUint {
+        let table = self
+            .arrangements_table(BigUint::zero(), BigUint::one(), |a, b| Some(a + b))
+            .unwrap();
+        table[0].clone()
+    }
+
+    /// Lazily generates all arrangements consistent with both the conditions and the broken groups.
+    /// Every generated arrangement consists only of operational and damaged springs.
+    ///
+    /// There may be exponentially many arrangements, so this is only practical for small rows.
+    /// However, no work is wasted on partial arrangements which can't be completed.
+    pub fn arrangements(&self) -> Arrangements<'_> {
+        let feasible = self
+            .arrangements_table(false, true, |a, b| Some(*a || *b))
+            .unwrap();
+        let stack = if feasible[0] {
+            vec![(0, 0, Vec::with_capacity(self.conditions.len()))]
+        } else {
+            Vec::default()
+        };
+
+        Arrangements {
+            row: self,
+            feasible,
+            non_operational_run: self.non_operational_runs(),
+            stack,
+        }
+    }
+
+    /// Checks if exactly one arrangement is consistent with both the conditions
+    /// and the broken groups - that is, if the row is a well-posed nonogram line.
+    pub fn has_unique_arrangement(&self) -> bool {
+        // Overflowing u128 is definitely not unique
+        self.count_arrangements() == Some(1)
+    }
+
+    /// Returns the only arrangement consistent with both the conditions and the broken groups,
+    /// or [None] if there are no such arrangements or more than one.
+    pub fn unique_arrangement(&self) -> Option<Vec<Condition>> {
+        let mut arrangements = self.arrangements();
+        let first = arrangements.next()?;
+        match arrangements.next() {
+            Some(_) => None,
+            None => Some(first),
+        }
+    }
+
+    /// Index of the DP table cell for the arrangements of `conditions[i..]` and `broken_groups[j..]`.
+    fn table_index(&self, i: usize, j: usize) -> usize {
+        i * (self.broken_groups.len() + 1) + j
+    }
+
+    /// Returns the number of consecutive non-operational springs starting at every position
+    /// (including the position just past the end).
+    fn non_operational_runs(&self) -> Vec<usize> {
+        let n = self.conditions.len();
+        let mut runs = vec![0; n + 1];
+        for i in (0..n).rev() {
+            if self.conditions[i] != Condition::Operational {
+                runs[i] = runs[i + 1] + 1;
+            }
+        }
+        runs
+    }
+
+    /// Checks if a broken group of the provided length can start at `conditions[i]`:
+    /// none of the springs in the group can be operational, and the group can't be
+    /// directly followed by a damaged spring.
+    fn group_fits(&self, i: usize, len: usize, non_operational_run: &[usize]) -> bool {
+        non_operational_run[i] >= len && self.conditions.get(i + len) != Some(&Condition::Damaged)
+    }
+
+    /// Computes the DP table, where the cell at [SpringsRow::table_index] (i, j) holds the
+    /// result of combining the arrangements of `conditions[i..]` and `broken_groups[j..]`.
+    /// `zero` is the result for impossible arrangements, `one` for exactly one arrangement,
+    /// and `add` combines results for alternatives (returning [None] on overflow).
+    fn arrangements_table<T: Clone>(
+        &self,
+        zero: T,
+        one: T,
+        add: impl Fn(&T, &T) -> Option<T>,
+    ) -> Option<Vec<T>> {
+        let n = self.conditions.len();
+        let m = self.broken_groups.len();
+
+        let non_operational_run = self.non_operational_runs();
+
+        let mut table = vec![zero.clone(); (n + 1) * (m + 1)];
+        table[self.table_index(n, m)] = one;
+
+        for i in (0..n).rev() {
+            for j in 0..=m {
+                let mut result = zero.clone();
+
+                // On '.' (or '?' substituted by '.') - this spring doesn't contribute to any group
+                if self.conditions[i] != Condition::Damaged {
+                    result = add(&result, &table[self.table_index(i + 1, j)])?;
+                }
+
+                // On '#' (or '?' substituted by '#') - the next group must start here
+                if j < m
+                    && self.conditions[i] != Condition::Operational
+                    && self.group_fits(i, self.broken_groups[j] as usize, &non_operational_run)
+                {
+                    let after = n.min(i + self.broken_groups[j] as usize + 1);
+                    result = add(&result, &table[self.table_index(after, j + 1)])?;
+                }
+
+                table[self.table_index(i, j)] = result;
+            }
+        }
+
+        Some(table)
+    }
+}
+
+/// Iterator over arrangements of a [SpringsRow], see [SpringsRow::arrangements].
+#[derive(Debug)]
+pub struct Arrangements<'a> {
+    row: &'a SpringsRow,
+    feasible: Vec<bool>,
+    non_operational_run: Vec<usize>,
+
+    /// Partial arrangements (of `conditions[..i]`, with `broken_groups[..j]` placed),
+    /// all of which can be completed.
+    stack: Vec<(usize, usize, Vec<Condition>)>,
+}
+
+impl Iterator for Arrangements<'_> {
+    type Item = Vec<Condition>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let conditions = &self.row.conditions;
+        let groups = &self.row.broken_groups;
+        let n = conditions.len();
+
+        while let Some((i, j, arrangement)) = self.stack.pop() {
+            if i == n {
+                return Some(arrangement);
+            }
+
+            // Push '#' first, so that '.' is explored first
+            if j < groups.len() && conditions[i] != Condition::Operational {
+                let len = groups[j] as usize;
+                let after = n.min(i + len + 1);
+                if self.row.group_fits(i, len, &self.non_operational_run)
+                    && self.feasible[self.row.table_index(after, j + 1)]
+                {
+                    let mut extended = arrangement.clone();
+                    extended.extend(std::iter::repeat_n(Condition::Damaged, len));
+                    if after > i + len {
+                        extended.push(Condition::Operational);
+                    }
+                    self.stack.push((after, j + 1, extended));
+                }
+            }
+
+            if conditions[i] != Condition::Damaged && self.feasible[self.row.table_index(i + 1, j)]
+            {
+                let mut extended = arrangement;
+                extended.push(Condition::Operational);
+                self.stack.push((i + 1, j, extended));
+            }
+        }
+
+        None
+    }
+}
+
+/// Returns all broken group lists achievable by substituting the unknown conditions,
+/// together with the number of substitutions which result in every list.
+///
+/// This is the inverse of [SpringsRow::count_arrangements]: for every returned `(groups, count)`,
+/// the row with `conditions` and `groups` has exactly `count` arrangements,
+/// and the counts sum up to 2 to the power of the number of unknown conditions.
+///
+/// The number of distinct group lists may grow exponentially with the number of unknown
+/// conditions, so this is only practical for short rows.
+pub fn group_lists(conditions: &[Condition]) -> HashMap<Vec<u8>, u128> {
+    // Maps (groups closed so far, length of the currently open group) to the number of
+    // substitutions leading to that state
+    let mut states: HashMap<(Vec<u8>, u8), u128> = HashMap::from([((Vec::default(), 0), 1)]);
+
+    for &condition in conditions {
+        let mut next_states: HashMap<(Vec<u8>, u8), u128> = HashMap::default();
+        for ((groups, open), count) in states {
+            if condition != Condition::Damaged {
+                let mut groups = groups.clone();
+                if open > 0 {
+                    groups.push(open);
+                }
+                *next_states.entry((groups, 0)).or_default() += count;
+            }
+
+            if condition != Condition::Operational {
+                let open = open
+                    .checked_add(1)
+                    .expect("broken group longer than 255 springs");
+                *next_states.entry((groups, open)).or_default() += count;
+            }
+        }
+        states = next_states;
+    }
+
+    let mut lists: HashMap<Vec<u8>, u128> = HashMap::default();
+    for ((mut groups, open), count) in states {
+        if open > 0 {
+            groups.push(open);
+        }
+        *lists.entry(groups).or_default() += count;
+    }
+    lists
+}
+
 pub fn load_input() -> Vec<SpringsRow> {
     stdin()
         .lines()
-        .map(|line| {
-            let line = line.expect("failed to read from stdin");
-            let (condition_str, broken_groups_str) = line.split_once(' ').unwrap();
-
-            let conditions = condition_str
-                .as_bytes()
-                .iter()
-                .copied()
-                .map(Condition::from_input_byte)
-                .collect();
+        .map(|line| SpringsRow::parse(&line.expect("failed to read from stdin")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::XorShift;
+
+    const SAMPLE: &str = "???.### 1,1,3
+.??..??...?##. 1,1,3
+?#?#?#?#?#?#?#? 1,3,1,6
+????.#...#... 4,1,1
+????.######..#####. 1,6,5
+?###???????? 3,2,1
+";
+
+    fn parse(text: &str) -> Vec<SpringsRow> {
+        text.lines().map(SpringsRow::parse).collect()
+    }
+
+    fn groups_of(arrangement: &[Condition]) -> Vec<u8> {
+        arrangement
+            .split(|&c| c == Condition::Operational)
+            .filter(|run| !run.is_empty())
+            .map(|run| run.len() as u8)
+            .collect()
+    }
+
+    /// Returns all substitutions of the unknown conditions, in lexicographic order
+    /// (with operational springs before damaged ones).
+    fn substitutions(conditions: &[Condition]) -> Vec<Vec<Condition>> {
+        let unknown = conditions
+            .iter()
+            .filter(|&&c| c == Condition::Unknown)
+            .count();
+        (0..1_u32 << unknown)
+            .map(|mask| {
+                let mut bit = unknown;
+                conditions
+                    .iter()
+                    .map(|&c| match c {
+                        Condition::Unknown => {
+                            bit -= 1;
+                            Condition::from_mask(mask & (1 << bit))
+                        }
+                        _ => c,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Generates a row with at most `max_len` springs, and groups taken from a random
+    /// substitution of its unknown conditions (or completely random groups).
+    fn generate_row(rng: &mut XorShift, max_len: u64) -> SpringsRow {
+        let len = 1 + rng.below(max_len) as usize;
+        let conditions: Vec<Condition> = (0..len)
+            .map(|_| match rng.below(4) {
+                0 => Condition::Operational,
+                1 => Condition::Damaged,
+                _ => Condition::Unknown,
+            })
+            .collect();
+
+        let broken_groups = if rng.below(4) == 0 {
+            (0..rng.below(4)).map(|_| 1 + rng.below(3) as u8).collect()
+        } else {
+            let all = substitutions(&conditions);
+            groups_of(&all[rng.below(all.len() as u64) as usize])
+        };
+
+        SpringsRow {
+            conditions,
+            broken_groups,
+        }
+    }
+
+    #[test]
+    fn count_arrangements_sample() {
+        let rows = parse(SAMPLE);
+        let counts: Vec<u128> = rows
+            .iter()
+            .map(|row| row.count_arrangements().unwrap())
+            .collect();
+        assert_eq!(counts, vec![1, 4, 1, 1, 4, 10]);
+        assert_eq!(counts.iter().sum::<u128>(), 21);
+
+        let unfolded: Vec<u128> = rows
+            .iter()
+            .map(|row| row.unfolded(5).count_arrangements().unwrap())
+            .collect();
+        assert_eq!(unfolded, vec![1, 16384, 1, 16, 2500, 506250]);
+        assert_eq!(unfolded.iter().sum::<u128>(), 525152);
+
+        for row in &rows {
+            assert_eq!(row.unfolded(1), *row);
+            assert_eq!(
+                row.unfolded(5).count_arrangements_big(),
+                BigUint::from(row.unfolded(5).count_arrangements().unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn count_arrangements_overflow() {
+        let row = SpringsRow::parse(&format!("{} {}", "?".repeat(400), vec!["1"; 50].join(",")));
+        assert_eq!(row.count_arrangements(), None);
 
-            let broken_groups = broken_groups_str
-                .split(',')
-                .map(|s| u8::from_str_radix(s, 10).unwrap())
+        // C(351, 50) - every group takes one of 351 slots, once the separators are removed
+        let expected = (0..50_u32).fold(BigUint::one(), |acc, k| acc * (351 - k) / (k + 1));
+        assert_eq!(row.count_arrangements_big(), expected);
+    }
+
+    #[test]
+    fn count_arrangements_brute_force() {
+        let mut rng = XorShift::new(0x2023_1212);
+        for _ in 0..500 {
+            let row = generate_row(&mut rng, 12);
+            let expected: Vec<Vec<Condition>> = substitutions(&row.conditions)
+                .into_iter()
+                .filter(|arrangement| groups_of(arrangement) == row.broken_groups)
                 .collect();
 
-            SpringsRow {
-                conditions,
-                broken_groups,
+            assert_eq!(
+                row.count_arrangements(),
+                Some(expected.len() as u128),
+                "{row:?}"
+            );
+            assert_eq!(row.arrangements().collect::<Vec<_>>(), expected, "{row:?}");
+        }
+    }
+
+    #[test]
+    fn unique_arrangement() {
+        let rows = parse(SAMPLE);
+        let row = &rows[0];
+        assert!(row.has_unique_arrangement());
+        assert_eq!(
+            row.unique_arrangement(),
+            Some(SpringsRow::parse("#.#.### 1,1,3").conditions)
+        );
+
+        // Several arrangements
+        assert!(!rows[1].has_unique_arrangement());
+        assert_eq!(rows[1].unique_arrangement(), None);
+
+        // No arrangements at all
+        let impossible = SpringsRow::parse("#.# 3");
+        assert!(!impossible.has_unique_arrangement());
+        assert_eq!(impossible.unique_arrangement(), None);
+
+        // Far too many arrangements to count in an u128
+        let huge = SpringsRow::parse(&format!("{} 1", "?".repeat(200)));
+        assert!(!huge.has_unique_arrangement());
+
+        let mut rng = XorShift::new(47);
+        for _ in 0..300 {
+            let row = generate_row(&mut rng, 10);
+            let unique = row.count_arrangements() == Some(1);
+            assert_eq!(row.has_unique_arrangement(), unique, "{row:?}");
+            assert_eq!(row.unique_arrangement().is_some(), unique, "{row:?}");
+        }
+    }
+
+    #[test]
+    fn group_lists_small() {
+        let conditions = SpringsRow::parse("#?# 1").conditions;
+        assert_eq!(
+            group_lists(&conditions),
+            HashMap::from([(vec![3], 1), (vec![1, 1], 1)])
+        );
+
+        let conditions = SpringsRow::parse("???.### 1").conditions;
+        let lists = group_lists(&conditions);
+        assert_eq!(lists.len(), 5);
+        assert_eq!(lists[&vec![1, 1, 3]], 1);
+        assert_eq!(lists[&vec![1, 3]], 3);
+        assert_eq!(lists[&vec![3]], 1);
+    }
+
+    #[test]
+    fn group_lists_brute_force() {
+        let mut rng = XorShift::new(0x1212);
+        for _ in 0..200 {
+            let conditions = generate_row(&mut rng, 12).conditions;
+            let lists = group_lists(&conditions);
+
+            let mut expected: HashMap<Vec<u8>, u128> = HashMap::default();
+            for arrangement in substitutions(&conditions) {
+                *expected.entry(groups_of(&arrangement)).or_default() += 1;
             }
-        })
-        .collect()
+            assert_eq!(lists, expected);
+
+            for (groups, count) in lists {
+                let row = SpringsRow {
+                    conditions: conditions.clone(),
+                    broken_groups: groups,
+                };
+                assert_eq!(row.count_arrangements(), Some(count));
+            }
+        }
+    }
 }
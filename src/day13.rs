@@ -36,20 +36,81 @@ pub fn is_symmetric_vertical(img: &Image, cutoff: usize) -> bool {
     return true;
 }
 
-pub fn find_reflection_line(img: &Image) -> usize {
-    for row in 1..img.len() {
-        if is_symmetric_horizontal(img, row) {
-            return 100 * row;
+/// Line of reflection in an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reflection {
+    /// Horizontal line of reflection, placed after the given number of rows.
+    Horizontal(usize),
+
+    /// Vertical line of reflection, placed after the given number of columns.
+    Vertical(usize),
+}
+
+impl Reflection {
+    /// Returns the puzzle's summary of the reflection: number of columns left of the line,
+    /// or 100 multiplied by the number of rows above the line.
+    pub fn summary(self) -> usize {
+        match self {
+            Reflection::Horizontal(rows) => 100 * rows,
+            Reflection::Vertical(cols) => cols,
+        }
+    }
+}
+
+/// Returns every candidate line of reflection (horizontal ones first), together with
+/// the number of cells which differ from their mirror image over that line.
+///
+/// All counts are computed in one pass over every pair of rows and every pair of columns;
+/// each pair contributes to the only line which maps its elements onto each other.
+pub fn mismatch_counts(img: &Image) -> Vec<(Reflection, usize)> {
+    let height = img.len();
+    let width = img.first().map_or(0, |row| row.len());
+
+    // Index i holds mismatches for the line after the i-th row/column
+    let mut horizontal = vec![0; height];
+    let mut vertical = vec![0; width];
+
+    for a in 0..height {
+        for b in ((a + 1)..height).step_by(2) {
+            horizontal[(a + b).div_ceil(2)] +=
+                img[a].iter().zip(&img[b]).filter(|(x, y)| x != y).count();
         }
     }
 
-    for col in 1..img[0].len() {
-        if is_symmetric_vertical(img, col) {
-            return col;
+    for row in img {
+        for a in 0..width {
+            for b in ((a + 1)..width).step_by(2) {
+                if row[a] != row[b] {
+                    vertical[(a + b).div_ceil(2)] += 1;
+                }
+            }
         }
     }
 
-    panic!("No symmetry in image!");
+    let horizontal = (1..height).map(|i| (Reflection::Horizontal(i), horizontal[i]));
+    let vertical = (1..width).map(|i| (Reflection::Vertical(i), vertical[i]));
+    horizontal.chain(vertical).collect()
+}
+
+/// Returns all lines of reflection (horizontal ones first) with exactly `smudges` cells
+/// differing from their mirror image.
+pub fn reflections(img: &Image, smudges: usize) -> Vec<Reflection> {
+    mismatch_counts(img)
+        .into_iter()
+        .filter(|&(_, mismatches)| mismatches == smudges)
+        .map(|(reflection, _)| reflection)
+        .collect()
+}
+
+/// Returns the first line of reflection (horizontal ones first) with exactly `smudges` cells
+/// differing from their mirror image, or [None] if there is no such line.
+pub fn find_reflection(img: &Image, smudges: usize) -> Option<Reflection> {
+    reflections(img, smudges).into_iter().next()
+}
+
+/// Returns the [Reflection::summary] of the perfect line of reflection, if there is one.
+pub fn find_reflection_line(img: &Image) -> Option<usize> {
+    find_reflection(img, 0).map(Reflection::summary)
 }
 
 pub fn load_input() -> Vec<Image> {
@@ -61,3 +122,139 @@ pub fn load_input() -> Vec<Image> {
         .map(|image| image.to_vec())
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::XorShift;
+
+    const SAMPLE: &str = "#.##..##.
+..#.##.#.
+##......#
+##......#
+..#.##.#.
+..##..##.
+#.##..##.
+
+#...##..#
+#....#..#
+..##..##.
+#####.##.
+#####.##.
+..##..##.
+#....#..#
+";
+
+    fn parse(text: &str) -> Vec<Image> {
+        text.split("\n\n")
+            .map(|image| image.lines().map(|line| line.as_bytes().to_vec()).collect())
+            .collect()
+    }
+
+    /// Counts mismatched cells for a line of reflection, comparing every cell with its mirror image.
+    fn count_mismatches(img: &Image, reflection: Reflection) -> usize {
+        let (height, width) = (img.len(), img[0].len());
+        let mut mismatches = 0;
+        for row in 0..height {
+            for col in 0..width {
+                let mirrored = match reflection {
+                    Reflection::Horizontal(after) => {
+                        (2 * after).checked_sub(row + 1).map(|r| (r, col))
+                    }
+                    Reflection::Vertical(after) => {
+                        (2 * after).checked_sub(col + 1).map(|c| (row, c))
+                    }
+                };
+                if let Some((r, c)) = mirrored.filter(|&(r, c)| r < height && c < width) {
+                    if img[row][col] != img[r][c] {
+                        mismatches += 1;
+                    }
+                }
+            }
+        }
+        mismatches / 2
+    }
+
+    #[test]
+    fn find_reflection_sample() {
+        let images = parse(SAMPLE);
+
+        let perfect: Vec<Option<Reflection>> =
+            images.iter().map(|img| find_reflection(img, 0)).collect();
+        assert_eq!(
+            perfect,
+            vec![
+                Some(Reflection::Vertical(5)),
+                Some(Reflection::Horizontal(4))
+            ]
+        );
+        assert_eq!(
+            perfect.iter().map(|r| r.unwrap().summary()).sum::<usize>(),
+            405
+        );
+        assert_eq!(
+            images
+                .iter()
+                .map(|img| find_reflection_line(img).unwrap())
+                .sum::<usize>(),
+            405
+        );
+
+        let smudged: Vec<Option<Reflection>> =
+            images.iter().map(|img| find_reflection(img, 1)).collect();
+        assert_eq!(
+            smudged,
+            vec![
+                Some(Reflection::Horizontal(3)),
+                Some(Reflection::Horizontal(1))
+            ]
+        );
+        assert_eq!(
+            smudged.iter().map(|r| r.unwrap().summary()).sum::<usize>(),
+            400
+        );
+    }
+
+    #[test]
+    fn find_reflection_missing() {
+        let img = parse("#.\n.#\n")[0].clone();
+        assert_eq!(find_reflection(&img, 0), None);
+        assert_eq!(find_reflection(&img, 1), None);
+        assert_eq!(find_reflection(&img, 2), Some(Reflection::Horizontal(1)));
+        assert_eq!(find_reflection_line(&img), None);
+        assert_eq!(
+            mismatch_counts(&img),
+            vec![(Reflection::Horizontal(1), 2), (Reflection::Vertical(1), 2)]
+        );
+    }
+
+    #[test]
+    fn mismatch_counts_brute_force() {
+        let mut rng = XorShift::new(13);
+        for _ in 0..200 {
+            let height = 1 + rng.below(8) as usize;
+            let width = 1 + rng.below(8) as usize;
+            let img: Image = (0..height)
+                .map(|_| {
+                    (0..width)
+                        .map(|_| if rng.below(3) == 0 { b'#' } else { b'.' })
+                        .collect()
+                })
+                .collect();
+
+            for (reflection, mismatches) in mismatch_counts(&img) {
+                assert_eq!(
+                    mismatches,
+                    count_mismatches(&img, reflection),
+                    "{reflection:?} in {img:?}"
+                );
+
+                let symmetric = match reflection {
+                    Reflection::Horizontal(after) => is_symmetric_horizontal(&img, after),
+                    Reflection::Vertical(after) => is_symmetric_vertical(&img, after),
+                };
+                assert_eq!(symmetric, mismatches == 0);
+            }
+        }
+    }
+}
@@ -1,7 +1,8 @@
 // Copyright (c) 2023 Mikołaj Kuranowski
 // SPDX-License-Identifier: MIT
 
-use std::fmt::{Debug, Write};
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Write};
 use std::io::stdin;
 use std::str::from_utf8_unchecked;
 
@@ -19,131 +20,351 @@ impl Debug for Platform {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Direction {
+    /// Parses the direction from its initial (`N`, `E`, `S` or `W`).
+    pub fn parse(c: char) -> Option<Self> {
+        match c {
+            'N' => Some(Self::North),
+            'E' => Some(Self::East),
+            'S' => Some(Self::South),
+            'W' => Some(Self::West),
+            _ => None,
+        }
+    }
+}
+
+/// Error returned by [Platform::run_tilts] when the tilt sequence contains
+/// something other than a direction initial.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidDirection(pub char);
+
+impl fmt::Display for InvalidDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid tilt direction: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidDirection {}
+
 impl Platform {
-    pub fn north_load(&self) -> usize {
-        let rows = self.0.len();
-        self.0
-            .iter()
-            .enumerate()
-            .map(|(row_idx, row)| {
-                let row_score = rows - row_idx;
-                row_score * row.iter().filter(|&&b| b == b'O').count()
+    pub fn new(rows: Vec<Vec<u8>>) -> Self {
+        Self(rows)
+    }
+
+    fn rows(&self) -> usize {
+        self.0.len()
+    }
+
+    fn columns(&self) -> usize {
+        self.0.first().map_or(0, |row| row.len())
+    }
+
+    /// Returns the number of lines (columns or rows) along which rocks move when tilting
+    /// in the given direction, and the length of those lines.
+    fn lines(&self, dir: Direction) -> (usize, usize) {
+        match dir {
+            Direction::North | Direction::South => (self.columns(), self.rows()),
+            Direction::East | Direction::West => (self.rows(), self.columns()),
+        }
+    }
+
+    /// Returns the (row, column) of the `pos`-th cell of a line,
+    /// counting from the side towards which the platform is tilted.
+    fn cell(&self, dir: Direction, line: usize, pos: usize) -> (usize, usize) {
+        match dir {
+            Direction::North => (pos, line),
+            Direction::South => (self.rows() - 1 - pos, line),
+            Direction::West => (line, pos),
+            Direction::East => (line, self.columns() - 1 - pos),
+        }
+    }
+
+    /// Returns the total load on the given side of the platform: every rock
+    /// contributes the number of cells between itself and the opposite side (inclusive).
+    pub fn load(&self, dir: Direction) -> usize {
+        let (lines, length) = self.lines(dir);
+        (0..lines)
+            .flat_map(|line| (0..length).map(move |pos| (line, pos)))
+            .filter(|&(line, pos)| {
+                let (row, col) = self.cell(dir, line, pos);
+                self.0[row][col] == b'O'
             })
+            .map(|(_, pos)| length - pos)
             .sum::<usize>()
     }
 
-    fn column_tilt_north(&mut self, col: usize) {
-        let mut insert_row: usize = 0;
+    pub fn tilt(&mut self, dir: Direction) {
+        let (lines, length) = self.lines(dir);
+
+        for line in 0..lines {
+            let mut insert_pos: usize = 0;
 
-        for row in 0..self.0.len() {
-            match self.0[row][col] {
-                b'O' => {
-                    if row != insert_row {
-                        self.0[row][col] = b'.';
-                        self.0[insert_row][col] = b'O';
+            for pos in 0..length {
+                let (row, col) = self.cell(dir, line, pos);
+                match self.0[row][col] {
+                    b'O' => {
+                        if pos != insert_pos {
+                            let (insert_row, insert_col) = self.cell(dir, line, insert_pos);
+                            self.0[row][col] = b'.';
+                            self.0[insert_row][insert_col] = b'O';
+                        }
+                        insert_pos += 1;
                     }
-                    insert_row += 1;
-                }
-                b'#' => {
-                    insert_row = row + 1;
+                    b'#' => {
+                        insert_pos = pos + 1;
+                    }
+                    _ => {}
                 }
-                _ => {}
             }
         }
     }
 
-    pub fn tilt_north(&mut self) {
-        for col in 0..self.0[0].len() {
-            self.column_tilt_north(col);
+    pub fn cycle(&mut self) {
+        self.tilt(Direction::North);
+        self.tilt(Direction::West);
+        self.tilt(Direction::South);
+        self.tilt(Direction::East);
+    }
+
+    /// Tilts the platform in the directions from `sequence` (like "NWSE"), with the whole
+    /// sequence repeated `times` times. Repeating states are detected, so that
+    /// `times` can be arbitrarily large. Fails on the first character of `sequence`
+    /// which isn't a direction initial.
+    ///
+    /// Platforms which fit in a [BitPlatform] are tilted in their bit-packed form.
+    pub fn run_tilts(&self, sequence: &str, times: u64) -> Result<Platform, InvalidDirection> {
+        let sequence = sequence
+            .chars()
+            .map(|c| Direction::parse(c).ok_or(InvalidDirection(c)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if self.columns() <= BitPlatform::MAX_WIDTH {
+            let result = run_tilts_with(BitPlatform::from(self), &sequence, times, |p, dir| {
+                p.tilt(dir)
+            });
+            Ok(Platform::from(&result))
+        } else {
+            Ok(run_tilts_with(self.clone(), &sequence, times, |p, dir| {
+                p.tilt(dir)
+            }))
         }
     }
+}
 
-    fn column_tilt_south(&mut self, col: usize) {
-        let mut insert_row: usize = self.0.len() - 1;
+/// Applies the tilt `sequence` to the platform `times` times, detecting repeating states.
+fn run_tilts_with<P, F>(mut platform: P, sequence: &[Direction], times: u64, tilt: F) -> P
+where
+    P: Clone + Eq + std::hash::Hash,
+    F: Fn(&mut P, Direction),
+{
+    let mut history: Vec<P> = Vec::default();
+    let mut seen: HashMap<P, u64> = HashMap::default();
 
-        for row in (0..self.0.len()).rev() {
-            match self.0[row][col] {
-                b'O' => {
-                    if row != insert_row {
-                        self.0[row][col] = b'.';
-                        self.0[insert_row][col] = b'O';
-                    }
-                    insert_row -= 1;
-                }
-                b'#' => {
-                    insert_row = row - 1;
-                }
-                _ => {}
-            }
+    for current in 0..times {
+        if let Some(&previous) = seen.get(&platform) {
+            // Platforms repeat - find the state equivalent to the target
+            let period = current - previous;
+            let equivalent = previous + (times - previous) % period;
+            return history.swap_remove(equivalent as usize);
         }
+
+        seen.insert(platform.clone(), current);
+        history.push(platform.clone());
+        sequence.iter().for_each(|&dir| tilt(&mut platform, dir));
+    }
+
+    platform
+}
+
+/// BitPlatform is a bit-packed [Platform], with every row stored as bitmasks
+/// of rocks and walls (bit `i` corresponding to column `i`). Platforms can be at most
+/// 128 columns wide.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct BitPlatform {
+    width: usize,
+    rocks: Vec<u128>,
+    walls: Vec<u128>,
+}
+
+impl Debug for BitPlatform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Platform::from(self).fmt(f)
     }
+}
 
-    pub fn tilt_south(&mut self) {
-        for col in 0..self.0[0].len() {
-            self.column_tilt_south(col);
+impl From<&Platform> for BitPlatform {
+    fn from(p: &Platform) -> Self {
+        let width = p.0.first().map_or(0, |row| row.len());
+        assert!(
+            width <= BitPlatform::MAX_WIDTH,
+            "platform too wide for a BitPlatform"
+        );
+
+        let row_mask = |row: &Vec<u8>, c: u8| {
+            row.iter()
+                .enumerate()
+                .filter(|&(_, &b)| b == c)
+                .fold(0, |mask, (col, _)| mask | (1 << col))
+        };
+
+        Self {
+            width,
+            rocks: p.0.iter().map(|row| row_mask(row, b'O')).collect(),
+            walls: p.0.iter().map(|row| row_mask(row, b'#')).collect(),
         }
     }
+}
 
-    fn row_tilt_west(&mut self, row: usize) {
-        let mut insert_col: usize = 0;
+impl From<&BitPlatform> for Platform {
+    fn from(p: &BitPlatform) -> Self {
+        Platform(
+            p.rocks
+                .iter()
+                .zip(&p.walls)
+                .map(|(&rocks, &walls)| {
+                    (0..p.width)
+                        .map(|col| {
+                            if rocks & (1 << col) != 0 {
+                                b'O'
+                            } else if walls & (1 << col) != 0 {
+                                b'#'
+                            } else {
+                                b'.'
+                            }
+                        })
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+}
 
-        for col in 0..self.0[0].len() {
-            match self.0[row][col] {
-                b'O' => {
-                    if col != insert_col {
-                        self.0[row][col] = b'.';
-                        self.0[row][insert_col] = b'O';
-                    }
-                    insert_col += 1;
-                }
-                b'#' => {
-                    insert_col = col + 1;
-                }
-                _ => {}
-            }
+impl BitPlatform {
+    pub const MAX_WIDTH: usize = 128;
+
+    /// Returns the total load on the given side of the platform, see [Platform::load].
+    pub fn load(&self, dir: Direction) -> usize {
+        let rows = self.rocks.len();
+        let rocks = self.rocks.iter().enumerate();
+        match dir {
+            Direction::North => rocks
+                .map(|(row, mask)| (rows - row) * mask.count_ones() as usize)
+                .sum(),
+            Direction::South => rocks
+                .map(|(row, mask)| (row + 1) * mask.count_ones() as usize)
+                .sum(),
+            Direction::West => (0..self.width)
+                .map(|col| (self.width - col) * self.column_rocks(col))
+                .sum(),
+            Direction::East => (0..self.width)
+                .map(|col| (col + 1) * self.column_rocks(col))
+                .sum(),
         }
     }
 
-    pub fn tilt_west(&mut self) {
-        for row in 0..self.0.len() {
-            self.row_tilt_west(row);
+    fn column_rocks(&self, col: usize) -> usize {
+        self.rocks
+            .iter()
+            .filter(|&&mask| mask & (1 << col) != 0)
+            .count()
+    }
+
+    pub fn tilt(&mut self, dir: Direction) {
+        match dir {
+            Direction::North => self.tilt_north(),
+            Direction::East => self.tilt_east(),
+            Direction::South => self.tilt_south(),
+            Direction::West => self.tilt_west(),
         }
     }
 
-    fn row_tilt_east(&mut self, row: usize) {
-        let mut insert_col: usize = self.0[0].len() - 1;
+    fn tilt_north(&mut self) {
+        self.tilt_vertical(true);
+    }
 
-        for col in (0..self.0[0].len()).rev() {
-            match self.0[row][col] {
-                b'O' => {
-                    if col != insert_col {
-                        self.0[row][col] = b'.';
-                        self.0[row][insert_col] = b'O';
-                    }
-                    insert_col -= 1;
-                }
-                b'#' => {
-                    insert_col = col - 1;
-                }
-                _ => {}
+    fn tilt_south(&mut self) {
+        self.tilt_vertical(false);
+    }
+
+    /// Moves all rocks north (or south), processing rows starting from the side towards
+    /// which the platform is tilted. Rows closer to that side are already settled,
+    /// so the rocks of a row roll one row at a time, all columns at once: the mask of rocks
+    /// which can still move shrinks with every step, until none of them can.
+    fn tilt_vertical(&mut self, north: bool) {
+        let rows = self.rocks.len();
+        let row_at = |pos: usize| if north { pos } else { rows - 1 - pos };
+
+        for pos in 1..rows {
+            let mut moving = self.rocks[row_at(pos)];
+            let mut from = pos;
+
+            while moving != 0 && from > 0 {
+                let (src, dst) = (row_at(from), row_at(from - 1));
+                moving &= !(self.rocks[dst] | self.walls[dst]);
+                self.rocks[src] &= !moving;
+                self.rocks[dst] |= moving;
+                from -= 1;
             }
         }
     }
 
-    pub fn tilt_east(&mut self) {
-        for row in 0..self.0.len() {
-            self.row_tilt_east(row);
+    fn tilt_west(&mut self) {
+        for (rocks, &walls) in self.rocks.iter_mut().zip(&self.walls) {
+            *rocks = slide_row(*rocks, walls, self.width, true);
+        }
+    }
+
+    fn tilt_east(&mut self) {
+        for (rocks, &walls) in self.rocks.iter_mut().zip(&self.walls) {
+            *rocks = slide_row(*rocks, walls, self.width, false);
         }
     }
 
     pub fn cycle(&mut self) {
-        self.tilt_north();
-        self.tilt_west();
-        self.tilt_south();
-        self.tilt_east();
+        self.tilt(Direction::North);
+        self.tilt(Direction::West);
+        self.tilt(Direction::South);
+        self.tilt(Direction::East);
     }
 }
 
+/// Returns a mask with bits from `start` (inclusive) to `end` (exclusive) set.
+fn bit_range(start: usize, end: usize) -> u128 {
+    if end - start == 128 {
+        u128::MAX
+    } else {
+        ((1 << (end - start)) - 1) << start
+    }
+}
+
+/// Moves all rocks in a row towards the lower bits (if `towards_low` is set)
+/// or towards the higher bits, by counting rocks between every pair of walls.
+fn slide_row(rocks: u128, walls: u128, width: usize, towards_low: bool) -> u128 {
+    let mut result = 0;
+    let mut start = 0;
+
+    while start < width {
+        let end = (start + (walls >> start).trailing_zeros() as usize).min(width);
+        let count = (rocks & bit_range(start, end)).count_ones() as usize;
+        result |= if towards_low {
+            bit_range(start, start + count)
+        } else {
+            bit_range(end - count, end)
+        };
+        start = end + 1;
+    }
+
+    result
+}
+
 pub fn load_input() -> Platform {
     Platform(
         stdin()
@@ -152,3 +373,128 @@ pub fn load_input() -> Platform {
             .collect(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "O....#....
+O.OO#....#
+.....##...
+OO.#O....O
+.O.....O#.
+O.#..O.#.#
+..O..#O..O
+.......O..
+#....###..
+#OO..#....
+";
+
+    const SAMPLE_AFTER_CYCLE: &str = ".....#....
+....#...O#
+...OO##...
+.OO#......
+.....OOO#.
+.O#...O#.#
+....O#....
+......OOOO
+#...O###..
+#..OO#....
+";
+
+    /// Irregular (non-square) platform with walls on all the edges and in the corners.
+    const IRREGULAR: &str = "#O.#O..O#O.O#
+O.O..#...O..O
+.O#O.O..O.#..
+#..O..OO.O..#
+O.O.#O.##.O.O
+#O...O..O.O.#
+";
+
+    fn parse(text: &str) -> Platform {
+        Platform::new(text.lines().map(|l| l.as_bytes().to_vec()).collect())
+    }
+
+    fn check_equivalent(platform: &Platform) {
+        assert_eq!(&Platform::from(&BitPlatform::from(platform)), platform);
+
+        for dir in [
+            Direction::North,
+            Direction::East,
+            Direction::South,
+            Direction::West,
+        ] {
+            let mut expected = platform.clone();
+            expected.tilt(dir);
+            let mut got = BitPlatform::from(platform);
+            got.tilt(dir);
+            assert_eq!(Platform::from(&got), expected, "tilting {dir:?}");
+            assert_eq!(got.load(dir), expected.load(dir), "load {dir:?}");
+        }
+
+        let mut expected = platform.clone();
+        let mut got = BitPlatform::from(platform);
+        for _ in 0..3 {
+            expected.cycle();
+            got.cycle();
+            assert_eq!(Platform::from(&got), expected);
+        }
+    }
+
+    #[test]
+    fn bit_platform_sample() {
+        let platform = parse(SAMPLE);
+        check_equivalent(&platform);
+
+        let mut got = BitPlatform::from(&platform);
+        got.cycle();
+        assert_eq!(Platform::from(&got), parse(SAMPLE_AFTER_CYCLE));
+    }
+
+    #[test]
+    fn bit_platform_irregular() {
+        check_equivalent(&parse(IRREGULAR));
+    }
+
+    #[test]
+    fn run_tilts_wide() {
+        // Too wide for a BitPlatform - every row is repeated 12 times
+        let wide = Platform::new(
+            parse(IRREGULAR)
+                .0
+                .iter()
+                .map(|row| row.repeat(12))
+                .collect(),
+        );
+
+        let mut expected = wide.clone();
+        for _ in 0..5 {
+            expected.cycle();
+        }
+        assert_eq!(wide.run_tilts("NWSE", 5), Ok(expected));
+    }
+
+    #[test]
+    fn run_tilts_sample() {
+        let platform = parse(SAMPLE);
+        assert_eq!(
+            platform.run_tilts("N", 1).unwrap().load(Direction::North),
+            136
+        );
+        assert_eq!(
+            platform
+                .run_tilts("NWSE", 1_000_000_000)
+                .unwrap()
+                .load(Direction::North),
+            64
+        );
+    }
+
+    #[test]
+    fn run_tilts_invalid_sequence() {
+        let platform = parse(SAMPLE);
+        assert_eq!(platform.run_tilts("NWXE", 1), Err(InvalidDirection('X')));
+        assert_eq!(platform.run_tilts("nwse", 1), Err(InvalidDirection('n')));
+        assert_eq!(platform.run_tilts("", 3), Ok(platform));
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright (c) 2023 Mikołaj Kuranowski
 // SPDX-License-Identifier: MIT
 
+use crate::bitset::BigBitset;
 use std::collections::HashMap;
 use std::io::stdin;
 
@@ -39,7 +40,140 @@ pub fn apply_dir(x: i16, y: i16, dir: Direction) -> (i16, i16, Direction) {
     }
 }
 
+/// Element describes how a tile of the contraption affects beams passing through it.
+pub trait Element {
+    /// Returns the directions (as a bitmask) in which beams leave the element,
+    /// after a beam enters it going in the `dir` direction.
+    fn outputs(&self, dir: Direction) -> Direction;
+
+    /// Returns the position from which the outgoing beams leave the element at (x, y).
+    fn exit_position(&self, x: i16, y: i16) -> (i16, i16) {
+        (x, y)
+    }
+}
+
+/// DirectionTable is an [Element] described by the outgoing directions
+/// for every incoming direction, indexed by the incoming direction's bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirectionTable(pub [Direction; 4]);
+
+impl DirectionTable {
+    pub const MIRROR_SLASH: Self = Self([DIR_RIGHT, DIR_UP, DIR_LEFT, DIR_DOWN]);
+    pub const MIRROR_BACKSLASH: Self = Self([DIR_LEFT, DIR_DOWN, DIR_RIGHT, DIR_UP]);
+    pub const SPLITTER_VERTICAL: Self =
+        Self([DIR_UP, DIR_UP | DIR_DOWN, DIR_DOWN, DIR_UP | DIR_DOWN]);
+    pub const SPLITTER_HORIZONTAL: Self = Self([
+        DIR_LEFT | DIR_RIGHT,
+        DIR_RIGHT,
+        DIR_LEFT | DIR_RIGHT,
+        DIR_LEFT,
+    ]);
+    pub const ABSORBER: Self = Self([0; 4]);
+}
+
+impl Element for DirectionTable {
+    fn outputs(&self, dir: Direction) -> Direction {
+        self.0[dir.trailing_zeros() as usize]
+    }
+}
+
+/// Teleport is an [Element] which moves incoming beams to its paired tile,
+/// from which they continue in the same direction. The paired tile itself
+/// is not energized, unless a beam enters it.
+#[derive(Debug, Clone, Default)]
+pub struct Teleport(HashMap<(i16, i16), (i16, i16)>);
+
+impl Teleport {
+    /// Pairs all `tile`s on the map in reading order - first with the second,
+    /// third with the fourth, and so on.
+    pub fn pairs(map: &Map, tile: u8) -> Self {
+        let positions: Vec<(i16, i16)> = (0..map.rows())
+            .flat_map(|x| (0..map.columns()).map(move |y| (x, y)))
+            .filter(|&(x, y)| map.0[x as usize][y as usize] == tile)
+            .collect();
+        assert!(positions.len().is_multiple_of(2), "unpaired teleport tile");
+
+        let mut partners = HashMap::default();
+        for pair in positions.chunks_exact(2) {
+            partners.insert(pair[0], pair[1]);
+            partners.insert(pair[1], pair[0]);
+        }
+        Self(partners)
+    }
+}
+
+impl Element for Teleport {
+    fn outputs(&self, dir: Direction) -> Direction {
+        dir
+    }
+
+    fn exit_position(&self, x: i16, y: i16) -> (i16, i16) {
+        self.0.get(&(x, y)).copied().unwrap_or((x, y))
+    }
+}
+
+/// Elements maps tiles to their [Element]s. Tiles without an element are treated as empty space.
+pub struct Elements(Vec<Option<Box<dyn Element>>>);
+
+impl Default for Elements {
+    /// Creates the elements described in the puzzle: mirrors ('/' and '\\') and splitters ('|' and '-').
+    fn default() -> Self {
+        let mut elements = Self::empty();
+        elements.register(b'/', DirectionTable::MIRROR_SLASH);
+        elements.register(b'\\', DirectionTable::MIRROR_BACKSLASH);
+        elements.register(b'|', DirectionTable::SPLITTER_VERTICAL);
+        elements.register(b'-', DirectionTable::SPLITTER_HORIZONTAL);
+        elements
+    }
+}
+
+impl std::fmt::Debug for Elements {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tiles = (0..=u8::MAX).filter(|&tile| self.0[tile as usize].is_some());
+        f.debug_set()
+            .entries(tiles.map(|tile| tile as char))
+            .finish()
+    }
+}
+
+impl Elements {
+    pub fn empty() -> Self {
+        Self((0..=u8::MAX).map(|_| None).collect())
+    }
+
+    pub fn register<E: Element + 'static>(&mut self, tile: u8, element: E) {
+        self.0[tile as usize] = Some(Box::new(element));
+    }
+
+    /// Returns the position from which beams leave the tile at (x, y),
+    /// and their directions (as a bitmask), after a beam enters it going in the `dir` direction.
+    pub fn exits(&self, tile: u8, x: i16, y: i16, dir: Direction) -> (i16, i16, Direction) {
+        match &self.0[tile as usize] {
+            Some(element) => {
+                let (exit_x, exit_y) = element.exit_position(x, y);
+                (exit_x, exit_y, element.outputs(dir))
+            }
+            None => (x, y, dir),
+        }
+    }
+}
+
+/// Iterates over every direction set in a bitmask.
+pub fn directions(mask: Direction) -> impl Iterator<Item = Direction> {
+    [DIR_UP, DIR_RIGHT, DIR_DOWN, DIR_LEFT]
+        .into_iter()
+        .filter(move |&dir| mask & dir != 0)
+}
+
 pub fn count_energy_tiles(map: &Map, entry: (i16, i16, Direction)) -> usize {
+    count_energy_tiles_with(map, &Elements::default(), entry)
+}
+
+pub fn count_energy_tiles_with(
+    map: &Map,
+    elements: &Elements,
+    entry: (i16, i16, Direction),
+) -> usize {
     let mut heads: Vec<(i16, i16, Direction)> = vec![entry];
     let mut visited: HashMap<(i16, i16), Direction> = HashMap::default();
 
@@ -57,62 +191,353 @@ pub fn count_energy_tiles(map: &Map, entry: (i16, i16, Direction)) -> usize {
         *visited_directions_here |= dir;
 
         // Advance the beam head
-        match map.0[x as usize][y as usize] {
-            b'/' => {
-                let new_dir = if dir & DIR_UP != 0 {
-                    DIR_RIGHT
-                } else if dir & DIR_LEFT != 0 {
-                    DIR_DOWN
-                } else if dir & DIR_RIGHT != 0 {
-                    DIR_UP
-                } else if dir & DIR_DOWN != 0 {
-                    DIR_LEFT
-                } else {
-                    panic!("invalid direction")
-                };
-                heads.push(apply_dir(x, y, new_dir));
+        let (exit_x, exit_y, outputs) = elements.exits(map.0[x as usize][y as usize], x, y, dir);
+        heads.extend(directions(outputs).map(|new_dir| apply_dir(exit_x, exit_y, new_dir)));
+    }
+
+    visited.len()
+}
+
+/// BeamTrace describes everything that happened to a beam inside the contraption.
+#[derive(Debug, Clone, Default)]
+pub struct BeamTrace {
+    /// Directions (as a bitmask) of all beams which have entered a tile, for every energized tile.
+    pub directions: HashMap<(i16, i16), Direction>,
+
+    /// Tiles where the beam was split, together with the direction of the incoming beam,
+    /// in the order of simulation.
+    pub splitter_activations: Vec<(i16, i16, Direction)>,
+
+    /// Every loop is a set of beam states (position and direction), where the beam
+    /// keeps on going in circles. Merging into an existing beam is not considered a loop.
+    pub loops: Vec<Vec<(i16, i16, Direction)>>,
+}
+
+impl BeamTrace {
+    pub fn energized(&self) -> usize {
+        self.directions.len()
+    }
+
+    /// Renders the beams like the puzzle statement does: empty tiles crossed by a single
+    /// beam are replaced by an arrow, and by the number of beams if crossed by more.
+    pub fn render(&self, map: &Map) -> String {
+        self.render_with(map, |tile, dirs| match (tile, dirs.count_ones()) {
+            (b'.', 0) => '.',
+            (b'.', 1) => match dirs {
+                DIR_UP => '^',
+                DIR_RIGHT => '>',
+                DIR_DOWN => 'v',
+                _ => '<',
+            },
+            (b'.', n) => char::from_digit(n, 10).unwrap(),
+            (tile, _) => tile as char,
+        })
+    }
+
+    /// Renders energized tiles as '#', and all other as '.'.
+    pub fn render_energized(&self, map: &Map) -> String {
+        self.render_with(map, |_, dirs| if dirs == 0 { '.' } else { '#' })
+    }
+
+    fn render_with<F: Fn(u8, Direction) -> char>(&self, map: &Map, f: F) -> String {
+        let mut s = String::with_capacity((map.columns() as usize + 1) * map.rows() as usize);
+        for x in 0..map.rows() {
+            for y in 0..map.columns() {
+                let dirs = self.directions.get(&(x, y)).copied().unwrap_or_default();
+                s.push(f(map.0[x as usize][y as usize], dirs));
             }
+            s.push('\n');
+        }
+        s
+    }
+}
 
-            b'\\' => {
-                let new_dir = if dir & DIR_UP != 0 {
-                    DIR_LEFT
-                } else if dir & DIR_RIGHT != 0 {
-                    DIR_DOWN
-                } else if dir & DIR_LEFT != 0 {
-                    DIR_UP
-                } else if dir & DIR_DOWN != 0 {
-                    DIR_RIGHT
-                } else {
-                    panic!("invalid direction")
-                };
-                heads.push(apply_dir(x, y, new_dir));
+pub fn trace_beam(map: &Map, entry: (i16, i16, Direction)) -> BeamTrace {
+    trace_beam_with(map, &Elements::default(), entry)
+}
+
+pub fn trace_beam_with(map: &Map, elements: &Elements, entry: (i16, i16, Direction)) -> BeamTrace {
+    let mut trace = BeamTrace::default();
+    let mut states: Vec<(i16, i16, Direction)> = Vec::default();
+    let mut state_ids: HashMap<(i16, i16, Direction), usize> = HashMap::default();
+    let mut edges: Vec<Vec<usize>> = Vec::default();
+    let mut heads: Vec<((i16, i16, Direction), Option<usize>)> = vec![(entry, None)];
+
+    while let Some(((x, y, dir), from)) = heads.pop() {
+        // Abort if out-of-bounds
+        if x < 0 || x >= map.rows() || y < 0 || y >= map.columns() {
+            continue;
+        }
+
+        // Remember how the beam got here
+        let id = *state_ids.entry((x, y, dir)).or_insert_with(|| {
+            states.push((x, y, dir));
+            edges.push(Vec::default());
+            states.len() - 1
+        });
+        if let Some(from) = from {
+            edges[from].push(id);
+        }
+
+        // Abort if already visited
+        let visited_directions_here = trace.directions.entry((x, y)).or_default();
+        if *visited_directions_here & dir != 0 {
+            continue;
+        }
+        *visited_directions_here |= dir;
+
+        // Advance the beam head
+        let (exit_x, exit_y, outputs) = elements.exits(map.0[x as usize][y as usize], x, y, dir);
+        if outputs.count_ones() > 1 {
+            trace.splitter_activations.push((x, y, dir));
+        }
+        heads.extend(
+            directions(outputs).map(|new_dir| (apply_dir(exit_x, exit_y, new_dir), Some(id))),
+        );
+    }
+
+    // Loops are exactly the non-trivial strongly connected components of visited states
+    trace.loops = strongly_connected_components(&edges)
+        .into_iter()
+        .filter(|component| component.len() > 1)
+        .map(|component| component.into_iter().map(|id| states[id]).collect())
+        .collect();
+
+    trace
+}
+
+/// BeamGraph speeds up [count_energy_tiles] for many entry points. Beams are only
+/// simulated between branching tiles (like splitters), and the tiles energized by each branch
+/// are precomputed for every strongly connected component of the graph of branches.
+#[derive(Debug)]
+pub struct BeamGraph<'a> {
+    map: &'a Map,
+    elements: Elements,
+    branch_ids: HashMap<(i16, i16, Direction), usize>,
+    reachable_tiles: Vec<BigBitset>,
+}
+
+/// Segment describes the path of a beam until it leaves the map, loops or branches.
+struct Segment {
+    tiles: BigBitset,
+    branch: Option<(i16, i16, Direction)>,
+}
+
+impl<'a> BeamGraph<'a> {
+    pub fn new(map: &'a Map) -> Self {
+        Self::with_elements(map, Elements::default())
+    }
+
+    pub fn with_elements(map: &'a Map, elements: Elements) -> Self {
+        // Find all branching states
+        let mut branches: Vec<(i16, i16, Direction)> = Vec::default();
+        for x in 0..map.rows() {
+            for y in 0..map.columns() {
+                for dir in directions(DIR_UP | DIR_RIGHT | DIR_DOWN | DIR_LEFT) {
+                    let (_, _, outputs) = elements.exits(map.0[x as usize][y as usize], x, y, dir);
+                    if outputs.count_ones() > 1 {
+                        branches.push((x, y, dir));
+                    }
+                }
+            }
+        }
+        let branch_ids: HashMap<(i16, i16, Direction), usize> = branches
+            .iter()
+            .enumerate()
+            .map(|(id, &b)| (b, id))
+            .collect();
+
+        // Simulate the beams leaving every branch
+        let mut tiles: Vec<BigBitset> = Vec::with_capacity(branches.len());
+        let mut edges: Vec<Vec<usize>> = Vec::with_capacity(branches.len());
+        for &(x, y, dir) in &branches {
+            let mut branch_tiles = BigBitset::default();
+            let mut branch_edges = Vec::default();
+            branch_tiles.insert(tile_index(map, x, y));
+
+            let (exit_x, exit_y, outputs) =
+                elements.exits(map.0[x as usize][y as usize], x, y, dir);
+            for new_dir in directions(outputs) {
+                let segment = trace_segment(map, &elements, apply_dir(exit_x, exit_y, new_dir));
+                branch_tiles.union_with(&segment.tiles);
+                branch_edges.extend(segment.branch.map(|b| branch_ids[&b]));
             }
 
-            b'|' => {
-                if dir & (DIR_LEFT | DIR_RIGHT) != 0 {
-                    heads.push(apply_dir(x, y, DIR_UP));
-                    heads.push(apply_dir(x, y, DIR_DOWN));
-                } else {
-                    heads.push(apply_dir(x, y, dir));
+            tiles.push(branch_tiles);
+            edges.push(branch_edges);
+        }
+
+        // Collapse the graph into strongly connected components - every branch in a component
+        // energizes exactly the same tiles. Tarjan's algorithm returns the components
+        // in reverse topological order, so successors are always processed first.
+        let components = strongly_connected_components(&edges);
+        let mut component_of = vec![0; branches.len()];
+        for (component_id, component) in components.iter().enumerate() {
+            for &branch in component {
+                component_of[branch] = component_id;
+            }
+        }
+
+        let mut reachable_tiles: Vec<BigBitset> = vec![BigBitset::default(); branches.len()];
+        for (component_id, component) in components.iter().enumerate() {
+            let mut component_tiles = BigBitset::default();
+            for &branch in component {
+                component_tiles.union_with(&tiles[branch]);
+                for &next in &edges[branch] {
+                    if component_of[next] != component_id {
+                        component_tiles.union_with(&reachable_tiles[next]);
+                    }
                 }
             }
+            for &branch in component {
+                reachable_tiles[branch] = component_tiles.clone();
+            }
+        }
+
+        Self {
+            map,
+            elements,
+            branch_ids,
+            reachable_tiles,
+        }
+    }
+
+    pub fn energized_tiles(&self, entry: (i16, i16, Direction)) -> BigBitset {
+        let mut segment = trace_segment(self.map, &self.elements, entry);
+        if let Some(branch) = segment.branch {
+            segment
+                .tiles
+                .union_with(&self.reachable_tiles[self.branch_ids[&branch]]);
+        }
+        segment.tiles
+    }
+
+    pub fn count_energy_tiles(&self, entry: (i16, i16, Direction)) -> usize {
+        self.energized_tiles(entry).len()
+    }
+}
+
+fn tile_index(map: &Map, x: i16, y: i16) -> u64 {
+    x as u64 * map.columns() as u64 + y as u64
+}
+
+fn trace_segment(
+    map: &Map,
+    elements: &Elements,
+    (mut x, mut y, mut dir): (i16, i16, Direction),
+) -> Segment {
+    let mut tiles = BigBitset::default();
+    let mut visited: HashMap<(i16, i16), Direction> = HashMap::default();
+
+    loop {
+        // Stop if out-of-bounds
+        if x < 0 || x >= map.rows() || y < 0 || y >= map.columns() {
+            return Segment {
+                tiles,
+                branch: None,
+            };
+        }
+
+        // Stop if the beam loops
+        let visited_directions_here = visited.entry((x, y)).or_default();
+        if *visited_directions_here & dir != 0 {
+            return Segment {
+                tiles,
+                branch: None,
+            };
+        }
+        *visited_directions_here |= dir;
+        tiles.insert(tile_index(map, x, y));
 
-            b'-' => {
-                if dir & (DIR_UP | DIR_DOWN) != 0 {
-                    heads.push(apply_dir(x, y, DIR_LEFT));
-                    heads.push(apply_dir(x, y, DIR_RIGHT));
+        // Stop on branches, otherwise advance the beam head
+        let (exit_x, exit_y, outputs) = elements.exits(map.0[x as usize][y as usize], x, y, dir);
+        if outputs.count_ones() != 1 {
+            return Segment {
+                tiles,
+                branch: if outputs == 0 {
+                    None
                 } else {
-                    heads.push(apply_dir(x, y, dir));
+                    Some((x, y, dir))
+                },
+            };
+        }
+        (x, y, dir) = apply_dir(exit_x, exit_y, outputs);
+    }
+}
+
+/// Finds strongly connected components with Tarjan's algorithm.
+/// The components are returned in reverse topological order.
+///
+/// The depth-first search uses an explicit stack instead of recursion,
+/// so that long chains of nodes can't overflow the call stack.
+fn strongly_connected_components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut index = vec![usize::MAX; edges.len()];
+    let mut low_link = vec![0; edges.len()];
+    let mut on_stack = vec![false; edges.len()];
+    let mut stack: Vec<usize> = Vec::default();
+    let mut next_index = 0;
+    let mut components: Vec<Vec<usize>> = Vec::default();
+
+    // (node, number of its edges already examined)
+    let mut call_stack: Vec<(usize, usize)> = Vec::default();
+
+    for root in 0..edges.len() {
+        if index[root] != usize::MAX {
+            continue;
+        }
+        call_stack.push((root, 0));
+
+        while let Some((v, edge)) = call_stack.pop() {
+            if edge == 0 {
+                // First time visiting v
+                index[v] = next_index;
+                low_link[v] = next_index;
+                next_index += 1;
+                stack.push(v);
+                on_stack[v] = true;
+            } else {
+                // Returning from the visit of the previous successor of v
+                let w = edges[v][edge - 1];
+                low_link[v] = low_link[v].min(low_link[w]);
+            }
+
+            // Examine the remaining successors, until one needs to be visited
+            let mut next_edge = edge;
+            let mut descend = None;
+            while next_edge < edges[v].len() {
+                let w = edges[v][next_edge];
+                next_edge += 1;
+                if index[w] == usize::MAX {
+                    descend = Some(w);
+                    break;
+                } else if on_stack[w] {
+                    low_link[v] = low_link[v].min(index[w]);
                 }
             }
 
-            _ => {
-                heads.push(apply_dir(x, y, dir));
+            if let Some(w) = descend {
+                call_stack.push((v, next_edge));
+                call_stack.push((w, 0));
+                continue;
+            }
+
+            // All successors examined - check if v is the root of a component
+            if low_link[v] == index[v] {
+                let mut component = Vec::default();
+                loop {
+                    let w = stack.pop().unwrap();
+                    on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                components.push(component);
             }
         }
     }
 
-    return visited.len();
+    components
 }
 
 pub fn load_input() -> Map {
@@ -121,3 +546,213 @@ pub fn load_input() -> Map {
         .map(|l| l.expect("failed to read from stdin").into_bytes())
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r".|...\....
+|.-.\.....
+.....|-...
+........|.
+..........
+.........\
+..../.\\..
+.-.-/..|..
+.|....-|.\
+..//.|....
+";
+
+    /// Map where a splitter sends the beam around a rectangular loop of mirrors.
+    const LOOP: &str = r"...|..
+./.-.\
+......
+.\.../
+";
+
+    /// Map where splitters send beams around in several loops.
+    const LOOPS: &str = r"..\..-..\.
+..|.....|.
+.-\-----/.
+.|...|..-.
+.\.-./....
+...|..\-/.
+.\-/......
+";
+
+    fn parse(text: &str) -> Map {
+        Map(text.lines().map(|l| l.as_bytes().to_vec()).collect())
+    }
+
+    fn edge_entries(map: &Map) -> Vec<(i16, i16, Direction)> {
+        let (rows, columns) = (map.rows(), map.columns());
+        (0..columns)
+            .flat_map(|y| [(0, y, DIR_DOWN), (rows - 1, y, DIR_UP)])
+            .chain((0..rows).flat_map(|x| [(x, 0, DIR_RIGHT), (x, columns - 1, DIR_LEFT)]))
+            .collect()
+    }
+
+    fn check_beam_graph(map: &Map) {
+        let graph = BeamGraph::new(map);
+        for entry in edge_entries(map) {
+            assert_eq!(
+                graph.count_energy_tiles(entry),
+                count_energy_tiles(map, entry),
+                "entry {entry:?}"
+            );
+        }
+    }
+
+    /// Splits incoming beams three ways - every direction except going back.
+    struct Prism;
+
+    impl Element for Prism {
+        fn outputs(&self, dir: Direction) -> Direction {
+            let back = ((dir << 2) | (dir >> 2)) & 0xF;
+            (DIR_UP | DIR_RIGHT | DIR_DOWN | DIR_LEFT) & !back
+        }
+    }
+
+    /// Default elements with an absorber ('X'), a prism ('P') and teleports ('T').
+    fn custom_elements(map: &Map) -> Elements {
+        let mut elements = Elements::default();
+        elements.register(b'X', DirectionTable::ABSORBER);
+        elements.register(b'P', Prism);
+        elements.register(b'T', Teleport::pairs(map, b'T'));
+        elements
+    }
+
+    fn energized(trace: &BeamTrace) -> Vec<(i16, i16)> {
+        let mut tiles: Vec<(i16, i16)> = trace.directions.keys().copied().collect();
+        tiles.sort();
+        tiles
+    }
+
+    #[test]
+    fn custom_absorber() {
+        let map = parse("..X..\n..|..\n");
+        let elements = custom_elements(&map);
+        assert_eq!(
+            count_energy_tiles_with(&map, &elements, (0, 0, DIR_RIGHT)),
+            3
+        );
+        assert_eq!(count_energy_tiles(&map, (0, 0, DIR_RIGHT)), 5);
+
+        // Splitter sends one of the beams into the absorber
+        let trace = trace_beam_with(&map, &elements, (1, 0, DIR_RIGHT));
+        assert_eq!(energized(&trace), vec![(0, 2), (1, 0), (1, 1), (1, 2)]);
+        assert_eq!(trace.splitter_activations, vec![(1, 2, DIR_RIGHT)]);
+    }
+
+    #[test]
+    fn custom_teleport() {
+        let map = parse(".T...\n.....\n...T.\n");
+        let elements = custom_elements(&map);
+
+        // The paired tile at (2, 3) isn't energized - the beam leaves it right away
+        let trace = trace_beam_with(&map, &elements, (0, 0, DIR_RIGHT));
+        assert_eq!(energized(&trace), vec![(0, 0), (0, 1), (2, 4)]);
+
+        let trace = trace_beam_with(&map, &elements, (2, 4, DIR_LEFT));
+        assert_eq!(energized(&trace), vec![(0, 0), (2, 3), (2, 4)]);
+
+        // Entering (2, 3) sends the beam on downwards from (0, 1)
+        let trace = trace_beam_with(&map, &elements, (1, 3, DIR_DOWN));
+        assert_eq!(energized(&trace), vec![(1, 1), (1, 3), (2, 1), (2, 3)]);
+        assert_eq!(
+            count_energy_tiles_with(&map, &elements, (1, 3, DIR_DOWN)),
+            4
+        );
+    }
+
+    #[test]
+    fn custom_elements_beam_graph() {
+        let map = parse(".....\n..P..\n.....\n");
+        let elements = custom_elements(&map);
+        assert_eq!(
+            count_energy_tiles_with(&map, &elements, (1, 0, DIR_RIGHT)),
+            7
+        );
+
+        let map = parse(
+            r"..X.T.\.
+.T.P..|.
+-..\./.X
+.P..-..|
+",
+        );
+        let graph = BeamGraph::with_elements(&map, custom_elements(&map));
+        let elements = custom_elements(&map);
+        for entry in edge_entries(&map) {
+            assert_eq!(
+                graph.count_energy_tiles(entry),
+                count_energy_tiles_with(&map, &elements, entry),
+                "entry {entry:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn beam_graph_sample() {
+        let map = parse(SAMPLE);
+        check_beam_graph(&map);
+        assert_eq!(
+            BeamGraph::new(&map).count_energy_tiles((0, 0, DIR_RIGHT)),
+            46
+        );
+    }
+
+    #[test]
+    fn beam_graph_loops() {
+        let map = parse(LOOP);
+        assert!(!trace_beam(&map, (0, 0, DIR_RIGHT)).loops.is_empty());
+        check_beam_graph(&map);
+        check_beam_graph(&parse(LOOPS));
+    }
+
+    /// Builds a `size` x `size` (size must be even) map with mirrors, which sends a beam entering
+    /// at (0, 1) right in a serpentine through every row, and back up along the first column.
+    fn serpentine(size: usize) -> Map {
+        let mut rows = vec![vec![b'.'; size]; size];
+        for (x, row) in rows.iter_mut().enumerate() {
+            row[size - 1] = if x % 2 == 0 { b'\\' } else { b'/' };
+            if x > 0 && x < size - 1 {
+                row[1] = if x % 2 == 0 { b'\\' } else { b'/' };
+            }
+        }
+        rows[0][0] = b'/';
+        rows[size - 1][0] = b'\\';
+        Map(rows)
+    }
+
+    #[test]
+    fn trace_beam_long_loop() {
+        let size = 200;
+        let map = serpentine(size);
+        let trace = trace_beam(&map, (0, 1, DIR_RIGHT));
+        assert_eq!(trace.energized(), size * size);
+        assert_eq!(trace.loops.len(), 1);
+        assert_eq!(trace.loops[0].len(), size * size);
+    }
+
+    #[test]
+    fn scc_long_chain() {
+        // A single cycle through a million nodes would overflow a recursive implementation
+        let n = 1_000_000;
+        let edges: Vec<Vec<usize>> = (0..n).map(|i| vec![(i + 1) % n]).collect();
+        let components = strongly_connected_components(&edges);
+        assert_eq!(components.len(), 1);
+        assert_eq!(components[0].len(), n);
+
+        // Reverse topological order: successors come first
+        let edges = vec![vec![1], vec![2, 3], vec![1], vec![]];
+        let components: Vec<Vec<usize>> = strongly_connected_components(&edges)
+            .into_iter()
+            .map(|mut c| {
+                c.sort();
+                c
+            })
+            .collect();
+        assert_eq!(components, vec![vec![3], vec![1, 2], vec![0]]);
+    }
+}
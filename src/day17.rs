@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: MIT
 
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 use std::io::stdin;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -14,6 +14,21 @@ pub enum Direction {
 }
 
 impl Direction {
+    pub const ALL: [Self; 4] = [Self::Up, Self::Right, Self::Down, Self::Left];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::Up => Self::Down,
+            Self::Right => Self::Left,
+            Self::Down => Self::Up,
+            Self::Left => Self::Right,
+        }
+    }
+
     pub fn next_steps(self) -> [Self; 3] {
         match self {
             Self::Up => [Self::Left, Self::Up, Self::Right],
@@ -38,7 +53,7 @@ impl Coords {
     }
 
     pub fn dist(self, other: Self) -> u16 {
-        (other.0 - self.0).abs() as u16 + (other.1 - self.1) as u16
+        (other.0 - self.0).unsigned_abs() + (other.1 - self.1).unsigned_abs()
     }
 }
 
@@ -46,6 +61,10 @@ impl Coords {
 pub struct Map(Vec<Vec<u8>>);
 
 impl Map {
+    pub fn new(tiles: Vec<Vec<u8>>) -> Self {
+        Self(tiles)
+    }
+
     pub fn rows(&self) -> i16 {
         self.0.len() as i16
     }
@@ -61,6 +80,10 @@ impl Map {
     pub fn at(&self, pt: Coords) -> u16 {
         self.0[pt.0 as usize][pt.1 as usize] as u16
     }
+
+    pub fn min_tile(&self) -> u16 {
+        self.0.iter().flatten().copied().min().unwrap_or(0) as u16
+    }
 }
 
 fn digit_to_value(x: &mut u8) {
@@ -83,10 +106,38 @@ pub fn load_input() -> Map {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct SearchNode {
-    coords: Coords,
-    dir: Direction,
-    steps: u8,
+pub struct SearchNode {
+    pub coords: Coords,
+    pub dir: Direction,
+    pub steps: u8,
+}
+
+/// Route is the cheapest path found by a [Search]. The first node is one of the starting points
+/// (with `steps` set to zero), the last one is one of the goals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub cost: u32,
+    pub nodes: Vec<SearchNode>,
+}
+
+impl Route {
+    /// Checks if the crucible moves at least `min_steps` before turning or stopping at the end,
+    /// and at most `max_steps` in a single direction.
+    pub fn check_steps(&self, min_steps: u8, max_steps: u8) -> bool {
+        let stops = self
+            .nodes
+            .last()
+            .is_some_and(|last| last.steps >= min_steps);
+        stops
+            && self.nodes.windows(2).all(|w| {
+                let (prev, next) = (w[0], w[1]);
+                if prev.dir == next.dir {
+                    next.steps == prev.steps + 1 && next.steps <= max_steps
+                } else {
+                    next.steps == 1 && prev.steps >= min_steps
+                }
+            })
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -109,21 +160,26 @@ impl Ord for SearchQueueEntry {
     }
 }
 
+/// ReferenceSearch is the original A* implementation, using a [BinaryHeap] and a [HashMap]
+/// of known costs. [Search] should be preferred, this implementation is only kept
+/// to verify and benchmark the faster one.
 #[derive(Debug)]
-pub struct Search<'a> {
+pub struct ReferenceSearch<'a> {
     map: &'a Map,
     known_costs: HashMap<SearchNode, u16>,
+    previous: HashMap<SearchNode, SearchNode>,
     queue: BinaryHeap<SearchQueueEntry>,
     end: Coords,
     min_steps: u8,
     max_steps: u8,
 }
 
-impl<'a> Search<'a> {
+impl<'a> ReferenceSearch<'a> {
     pub fn new(map: &'a Map, min_steps: u8, max_steps: u8) -> Self {
         Self {
-            map: map,
+            map,
             known_costs: HashMap::default(),
+            previous: HashMap::default(),
             queue: BinaryHeap::default(),
             end: Coords(map.rows() - 1, map.columns() - 1),
             min_steps,
@@ -152,13 +208,28 @@ impl<'a> Search<'a> {
         self.add_initial_entry(Direction::Down);
     }
 
-    pub fn run(&mut self) -> u16 {
+    fn route_to(&self, mut node: SearchNode, cost: u16) -> Route {
+        let mut nodes = vec![node];
+        while let Some(&prev) = self.previous.get(&node) {
+            nodes.push(prev);
+            node = prev;
+        }
+        nodes.reverse();
+        Route {
+            cost: cost.into(),
+            nodes,
+        }
+    }
+
+    /// Finds the cheapest route from the top-left to the bottom-right corner of the map,
+    /// or None if the end can't be reached.
+    pub fn run(&mut self) -> Option<Route> {
         self.add_initial_entries();
 
         while let Some(entry) = self.queue.pop() {
-            // End reached
-            if entry.node.coords == self.end {
-                return entry.cost;
+            // End reached - the crucible must be able to stop there
+            if entry.node.coords == self.end && entry.node.steps >= self.min_steps {
+                return Some(self.route_to(entry.node, entry.cost));
             }
 
             // Add neighbors to the queue
@@ -197,6 +268,7 @@ impl<'a> Search<'a> {
 
                 // Push to queue
                 *known_cost = next_cost;
+                self.previous.insert(next_node, entry.node);
                 self.queue.push(SearchQueueEntry {
                     node: next_node,
                     cost: next_cost,
@@ -205,6 +277,471 @@ impl<'a> Search<'a> {
             }
         }
 
-        panic!("No route found :^(");
+        None
+    }
+}
+
+/// Rules describe how the crucible may move during a [Search].
+#[derive(Debug, Clone)]
+pub struct Rules {
+    pub start: Vec<Coords>,
+    pub goal: HashSet<Coords>,
+
+    /// Directions the crucible may be facing at the start.
+    pub initial_directions: Vec<Direction>,
+
+    /// Minimum number of steps in a single direction, before the crucible can turn.
+    pub min_steps: u8,
+
+    /// Maximum number of steps in a single direction.
+    pub max_steps: u8,
+
+    /// Allows the crucible to reverse its direction, subject to the same limits as turning.
+    pub allow_u_turns: bool,
+
+    /// Extra cost incurred on every turn (including U-turns).
+    pub turn_cost: u32,
+
+    pub impassable: HashSet<Coords>,
+}
+
+impl Rules {
+    /// Creates rules for going from the top-left to the bottom-right corner of the map,
+    /// starting either rightwards or downwards.
+    pub fn new(map: &Map, min_steps: u8, max_steps: u8) -> Self {
+        Self {
+            start: vec![Coords(0, 0)],
+            goal: HashSet::from([Coords(map.rows() - 1, map.columns() - 1)]),
+            initial_directions: vec![Direction::Right, Direction::Down],
+            min_steps,
+            max_steps,
+            allow_u_turns: false,
+            turn_cost: 0,
+            impassable: HashSet::default(),
+        }
+    }
+
+    /// Rules of the regular crucible from part A.
+    pub fn part_a(map: &Map) -> Self {
+        Self::new(map, 0, 3)
+    }
+
+    /// Rules of the ultra crucible from part B.
+    pub fn part_b(map: &Map) -> Self {
+        Self::new(map, 4, 10)
+    }
+
+    fn next_directions(&self, dir: Direction) -> impl Iterator<Item = Direction> {
+        let u_turn = if self.allow_u_turns {
+            Some(dir.opposite())
+        } else {
+            None
+        };
+        dir.next_steps().into_iter().chain(u_turn)
+    }
+}
+
+/// Search finds the cheapest route using A* with a bucket queue (indexed by the score),
+/// with costs and predecessors of all search nodes kept in flat arrays.
+#[derive(Debug)]
+pub struct Search<'a> {
+    map: &'a Map,
+    rules: Rules,
+    is_goal: Vec<bool>,
+    is_impassable: Vec<bool>,
+    heuristic: Vec<u32>,
+    known_costs: Vec<u32>,
+    previous: Vec<usize>,
+    buckets: Vec<Vec<usize>>,
+}
+
+impl<'a> Search<'a> {
+    const NO_PREVIOUS: usize = usize::MAX;
+
+    pub fn new(map: &'a Map, rules: Rules) -> Self {
+        let cells = map.rows() as usize * map.columns() as usize;
+        let nodes = cells * 4 * (rules.max_steps as usize + 1);
+
+        let mut is_goal = vec![false; cells];
+        let mut is_impassable = vec![false; cells];
+        for &pt in rules.goal.iter().filter(|&&pt| map.in_bounds(pt)) {
+            is_goal[Self::cell_index(map, pt)] = true;
+        }
+        for &pt in rules.impassable.iter().filter(|&&pt| map.in_bounds(pt)) {
+            is_impassable[Self::cell_index(map, pt)] = true;
+        }
+
+        Self {
+            map,
+            heuristic: Self::compute_heuristic(map, &is_goal, &is_impassable),
+            rules,
+            is_goal,
+            is_impassable,
+            known_costs: vec![u32::MAX; nodes],
+            previous: vec![Self::NO_PREVIOUS; nodes],
+            buckets: Vec::default(),
+        }
+    }
+
+    fn cell_index(map: &Map, pt: Coords) -> usize {
+        pt.0 as usize * map.columns() as usize + pt.1 as usize
+    }
+
+    /// Computes the lower bound of the cost from every cell to the closest goal:
+    /// the number of steps around impassable tiles (calculated with a breadth-first search
+    /// from all goals at once), multiplied by the cheapest tile. Impassable cells
+    /// and cells from which no goal can be reached get u32::MAX.
+    fn compute_heuristic(map: &Map, is_goal: &[bool], is_impassable: &[bool]) -> Vec<u32> {
+        let rows = map.rows() as usize;
+        let columns = map.columns() as usize;
+        let mut dist: Vec<u32> = vec![u32::MAX; is_goal.len()];
+        let mut queue: VecDeque<usize> = VecDeque::default();
+
+        for idx in 0..is_goal.len() {
+            if is_goal[idx] && !is_impassable[idx] {
+                dist[idx] = 0;
+                queue.push_back(idx);
+            }
+        }
+
+        while let Some(idx) = queue.pop_front() {
+            let (row, col) = (idx / columns, idx % columns);
+            let neighbors = [
+                (row > 0).then(|| idx - columns),
+                (row + 1 < rows).then(|| idx + columns),
+                (col > 0).then(|| idx - 1),
+                (col + 1 < columns).then(|| idx + 1),
+            ];
+
+            for next in neighbors.into_iter().flatten() {
+                if !is_impassable[next] && dist[next] == u32::MAX {
+                    dist[next] = dist[idx] + 1;
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        let min_tile = u32::from(map.min_tile());
+        dist.iter()
+            .map(|&d| match d {
+                u32::MAX => u32::MAX,
+                d => d.saturating_mul(min_tile).min(u32::MAX - 1),
+            })
+            .collect()
+    }
+
+    fn node_index(&self, node: SearchNode) -> usize {
+        let cell = Self::cell_index(self.map, node.coords);
+        (cell * 4 + node.dir.index()) * (self.rules.max_steps as usize + 1) + node.steps as usize
+    }
+
+    fn node_at(&self, idx: usize) -> SearchNode {
+        let steps = idx % (self.rules.max_steps as usize + 1);
+        let idx = idx / (self.rules.max_steps as usize + 1);
+        let dir = Direction::ALL[idx % 4];
+        let cell = idx / 4;
+        let columns = self.map.columns() as usize;
+        SearchNode {
+            coords: Coords((cell / columns) as i16, (cell % columns) as i16),
+            dir,
+            steps: steps as u8,
+        }
+    }
+
+    fn score(&self, node: SearchNode, cost: u32) -> Option<usize> {
+        match self.heuristic[Self::cell_index(self.map, node.coords)] {
+            u32::MAX => None,
+            h => Some(cost as usize + h as usize),
+        }
+    }
+
+    fn push(&mut self, node: SearchNode, cost: u32, previous: usize) {
+        let idx = self.node_index(node);
+        if cost >= self.known_costs[idx] {
+            return;
+        }
+
+        // Don't bother with nodes from which no goal can be reached
+        let Some(score) = self.score(node, cost) else {
+            return;
+        };
+
+        self.known_costs[idx] = cost;
+        self.previous[idx] = previous;
+
+        if score >= self.buckets.len() {
+            self.buckets.resize_with(score + 1, Vec::default);
+        }
+        self.buckets[score].push(idx);
+    }
+
+    fn route_to(&self, mut idx: usize) -> Route {
+        let cost = self.known_costs[idx];
+        let mut nodes = vec![self.node_at(idx)];
+        while self.previous[idx] != Self::NO_PREVIOUS {
+            idx = self.previous[idx];
+            nodes.push(self.node_at(idx));
+        }
+        nodes.reverse();
+        Route { cost, nodes }
+    }
+
+    fn add_initial_entries(&mut self) {
+        let starts = self.rules.start.clone();
+        let directions = self.rules.initial_directions.clone();
+
+        for coords in starts {
+            if !self.map.in_bounds(coords) || self.is_impassable[Self::cell_index(self.map, coords)]
+            {
+                continue;
+            }
+
+            for &dir in &directions {
+                let node = SearchNode {
+                    coords,
+                    dir,
+                    steps: 0,
+                };
+                self.push(node, 0, Self::NO_PREVIOUS);
+            }
+        }
+    }
+
+    /// Finds the cheapest route from any of the starting points to any of the goals,
+    /// or None if no goal can be reached.
+    pub fn run(&mut self) -> Option<Route> {
+        self.add_initial_entries();
+
+        let mut score = 0;
+        while score < self.buckets.len() {
+            while let Some(idx) = self.buckets[score].pop() {
+                let node = self.node_at(idx);
+                let cost = self.known_costs[idx];
+
+                // Skip outdated entries - the node was re-added with a lower score
+                if self.score(node, cost) != Some(score) {
+                    continue;
+                }
+
+                // Goal reached - the crucible must be able to stop there,
+                // after moving at least min_steps in the last direction
+                if self.is_goal[Self::cell_index(self.map, node.coords)]
+                    && node.steps >= self.rules.min_steps
+                {
+                    return Some(self.route_to(idx));
+                }
+
+                // Add neighbors to the queue
+                for next_dir in self.rules.next_directions(node.dir) {
+                    let next_coords = node.coords.moved(next_dir);
+
+                    // Don't go beyond the map or onto impassable tiles
+                    if !self.map.in_bounds(next_coords)
+                        || self.is_impassable[Self::cell_index(self.map, next_coords)]
+                    {
+                        continue;
+                    }
+
+                    // Check if the step conforms to the requirements
+                    let same_dir = node.dir == next_dir;
+                    if (same_dir && node.steps >= self.rules.max_steps)
+                        || (!same_dir && node.steps < self.rules.min_steps)
+                    {
+                        continue;
+                    }
+
+                    let next_node = SearchNode {
+                        coords: next_coords,
+                        dir: next_dir,
+                        steps: if same_dir { node.steps + 1 } else { 1 },
+                    };
+                    let mut next_cost = cost + u32::from(self.map.at(next_coords));
+                    if !same_dir {
+                        next_cost += self.rules.turn_cost;
+                    }
+                    self.push(next_node, next_cost, idx);
+                }
+            }
+
+            score += 1;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::XorShift;
+    use std::cmp::Reverse;
+
+    /// Cost, row, column, direction index and steps of a search node.
+    type QueueEntry = Reverse<(u32, i16, i16, usize, u8)>;
+
+    const SAMPLE: &str = "2413432311323
+3215453535623
+3255245654254
+3446585845452
+4546657867536
+1438598798454
+4457876987766
+3637877979653
+4654967986887
+4564679986453
+1224686865563
+2546548887735
+4322674655533
+";
+
+    fn parse(text: &str) -> Map {
+        Map::new(
+            text.lines()
+                .map(|line| line.bytes().map(|c| c - b'0').collect())
+                .collect(),
+        )
+    }
+
+    /// Returns a random `size` x `size` map and random impassable tiles (about a quarter of them).
+    fn generate(size: usize, seed: u64) -> (Map, HashSet<Coords>) {
+        let mut rng = XorShift::new(seed);
+        let mut next = |n: u64| rng.below(n);
+
+        let tiles: Vec<Vec<u8>> = (0..size)
+            .map(|_| (0..size).map(|_| next(9) as u8 + 1).collect())
+            .collect();
+        let impassable = (0..size * size)
+            .filter(|_| next(4) == 0)
+            .map(|i| Coords((i / size) as i16, (i % size) as i16))
+            .collect();
+        (Map::new(tiles), impassable)
+    }
+
+    /// Plain Dijkstra's algorithm over all search nodes, without any heuristic.
+    fn cheapest_cost(map: &Map, rules: &Rules) -> Option<u32> {
+        let mut known_costs: HashMap<SearchNode, u32> = HashMap::default();
+        let mut queue: BinaryHeap<QueueEntry> = BinaryHeap::default();
+
+        for &coords in &rules.start {
+            if rules.impassable.contains(&coords) {
+                continue;
+            }
+            for &dir in &rules.initial_directions {
+                queue.push(Reverse((0, coords.0, coords.1, dir.index(), 0)));
+            }
+        }
+
+        while let Some(Reverse((cost, row, col, dir, steps))) = queue.pop() {
+            let node = SearchNode {
+                coords: Coords(row, col),
+                dir: Direction::ALL[dir],
+                steps,
+            };
+            if known_costs.get(&node).is_some_and(|&known| known <= cost) {
+                continue;
+            }
+            known_costs.insert(node, cost);
+
+            if rules.goal.contains(&node.coords) && steps >= rules.min_steps {
+                return Some(cost);
+            }
+
+            for next_dir in rules.next_directions(node.dir) {
+                let next = node.coords.moved(next_dir);
+                let same_dir = next_dir == node.dir;
+                if !map.in_bounds(next)
+                    || rules.impassable.contains(&next)
+                    || (same_dir && steps >= rules.max_steps)
+                    || (!same_dir && steps < rules.min_steps)
+                {
+                    continue;
+                }
+
+                let turn_cost = if same_dir { 0 } else { rules.turn_cost };
+                queue.push(Reverse((
+                    cost + u32::from(map.at(next)) + turn_cost,
+                    next.0,
+                    next.1,
+                    next_dir.index(),
+                    if same_dir { steps + 1 } else { 1 },
+                )));
+            }
+        }
+
+        None
+    }
+
+    #[test]
+    fn search_sample() {
+        let map = parse(SAMPLE);
+        let route = Search::new(&map, Rules::part_a(&map)).run().unwrap();
+        assert_eq!(route.cost, 102);
+        let route = Search::new(&map, Rules::part_b(&map)).run().unwrap();
+        assert_eq!(route.cost, 94);
+    }
+
+    #[test]
+    fn search_part_b_stops_after_min_steps() {
+        let map = parse("111111111111\n999999999991\n999999999991\n999999999991\n999999999991\n");
+        let route = Search::new(&map, Rules::part_b(&map)).run().unwrap();
+        assert_eq!(route.cost, 71);
+        assert!(route.check_steps(4, 10));
+
+        let route = ReferenceSearch::new(&map, 4, 10).run().unwrap();
+        assert_eq!(route.cost, 71);
+    }
+
+    #[test]
+    fn check_steps_final_segment() {
+        let node = |col: i16, dir: Direction, steps: u8| SearchNode {
+            coords: Coords(0, col),
+            dir,
+            steps,
+        };
+        let mut route = Route {
+            cost: 0,
+            nodes: vec![node(0, Direction::Right, 0)],
+        };
+        route
+            .nodes
+            .extend((1..=5).map(|i| node(i, Direction::Right, i as u8)));
+        assert!(route.check_steps(4, 10));
+        assert!(route.check_steps(5, 10));
+        assert!(!route.check_steps(6, 10));
+        assert!(!route.check_steps(0, 4));
+    }
+
+    #[test]
+    fn search_impassable() {
+        for seed in 1..=40 {
+            let (map, impassable) = generate(12, seed);
+            for (min_steps, max_steps) in [(0, 3), (1, 4), (4, 10)] {
+                let mut rules = Rules::new(&map, min_steps, max_steps);
+                rules.impassable = impassable.clone();
+                rules.allow_u_turns = seed % 2 == 0;
+                rules.turn_cost = (seed % 3) as u32;
+
+                let expected = cheapest_cost(&map, &rules);
+                let route = Search::new(&map, rules).run();
+                assert_eq!(route.as_ref().map(|r| r.cost), expected, "seed {seed}");
+                if let Some(route) = route {
+                    assert!(route.check_steps(min_steps, max_steps));
+                    assert!(route.nodes.iter().all(|n| !impassable.contains(&n.coords)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn heuristic_walled_off_goal() {
+        let map = parse(SAMPLE);
+        let mut rules = Rules::part_a(&map);
+        let goal = Coords(map.rows() - 1, map.columns() - 1);
+        rules.impassable = HashSet::from([goal.moved(Direction::Up), goal.moved(Direction::Left)]);
+
+        let mut search = Search::new(&map, rules);
+        assert_eq!(search.heuristic[0], u32::MAX);
+        assert_eq!(search.heuristic[Search::cell_index(&map, goal)], 0);
+        assert!(search.run().is_none());
     }
 }
@@ -1,7 +1,10 @@
 // Copyright (c) 2023 Mikołaj Kuranowski
 // SPDX-License-Identifier: MIT
 
-#[derive(Debug, Clone, Copy)]
+use std::fmt;
+use std::io::{stdin, Read, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Up,
     Right,
@@ -9,11 +12,36 @@ pub enum Direction {
     Left,
 }
 
+impl Direction {
+    /// Parses the direction as written in the dig plan (`U`, `R`, `D` or `L`).
+    pub fn parse(c: char) -> Option<Self> {
+        match c {
+            'U' => Some(Direction::Up),
+            'R' => Some(Direction::Right),
+            'D' => Some(Direction::Down),
+            'L' => Some(Direction::Left),
+            _ => None,
+        }
+    }
+
+    /// Decodes the direction from the last hex digit of a color
+    /// (`0` means right, `1` down, `2` left and `3` up).
+    pub fn from_digit(digit: u32) -> Option<Self> {
+        match digit {
+            0 => Some(Direction::Right),
+            1 => Some(Direction::Down),
+            2 => Some(Direction::Left),
+            3 => Some(Direction::Up),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Coords(pub i32, pub i32);
+pub struct Coords(pub i64, pub i64);
 
 impl Coords {
-    pub fn moved(self, dir: Direction, step: i32) -> Self {
+    pub fn moved(self, dir: Direction, step: i64) -> Self {
         match dir {
             Direction::Up => Coords(self.0 - step, self.1),
             Direction::Right => Coords(self.0, self.1 + step),
@@ -52,9 +80,8 @@ impl Coords {
             }
 
             if (polygon[i].1 > self.1) != (polygon[j].1 > self.1) {
-                let slope = (self.0 - polygon[i].0) as isize
-                    * (polygon[j].1 - polygon[i].1) as isize
-                    - (polygon[j].0 - polygon[i].0) as isize * (self.1 - polygon[i].1) as isize;
+                let slope = (self.0 - polygon[i].0) as i128 * (polygon[j].1 - polygon[i].1) as i128
+                    - (polygon[j].0 - polygon[i].0) as i128 * (self.1 - polygon[i].1) as i128;
 
                 // The slope == 0 check is replaced with the more robust checks above.
 
@@ -70,40 +97,391 @@ impl Coords {
 #[derive(Debug)]
 pub struct PlanEntry {
     pub dir: Direction,
-    pub step: i32,
+    pub step: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanError {
+    /// The color of the instruction with the given index encodes an unknown direction.
+    InvalidColorDirection(usize),
+
+    /// The plan does not return to the starting point; it ends at the given coordinates.
+    NotClosed(Coords),
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanError::InvalidColorDirection(i) => {
+                write!(f, "color of instruction {i} encodes an invalid direction")
+            }
+            PlanError::NotClosed(end) => write!(
+                f,
+                "dig plan does not close: ends at ({}, {}) instead of (0, 0)",
+                end.0, end.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+/// Color of a dig plan instruction, packed as `0xRRGGBB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color(pub u32);
+
+impl Color {
+    pub fn rgb(self) -> [u8; 3] {
+        [(self.0 >> 16) as u8, (self.0 >> 8) as u8, self.0 as u8]
+    }
+}
+
+/// Which part of a [DigInstruction] describes where to dig.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpretation {
+    /// Use the written direction and step (part A).
+    Written,
+
+    /// Use the first 5 hex digits of the color as the step
+    /// and the last one as the direction (part B).
+    Color,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DigInstruction {
+    pub dir: Direction,
+    pub step: i64,
+    pub color: Color,
+}
+
+impl DigInstruction {
+    /// Parses a single line of the dig plan, e.g. `R 6 (#70c710)`.
+    pub fn parse(line: &str) -> Self {
+        let mut parts = line.split_ascii_whitespace();
+
+        let dir_str = parts.next().expect("missing direction");
+        let mut dir_chars = dir_str.chars();
+        let dir = dir_chars
+            .next()
+            .and_then(Direction::parse)
+            .filter(|_| dir_chars.next().is_none())
+            .unwrap_or_else(|| panic!("invalid direction: {dir_str:?}"));
+
+        let step = parts
+            .next()
+            .expect("missing step")
+            .parse()
+            .expect("invalid step");
+
+        let color_str = parts.next().expect("missing color");
+        let hex = color_str
+            .strip_prefix("(#")
+            .and_then(|x| x.strip_suffix(')'))
+            .filter(|x| x.len() == 6)
+            .unwrap_or_else(|| panic!("invalid color: {color_str:?}"));
+        let color = Color(u32::from_str_radix(hex, 16).expect("invalid color"));
+
+        assert!(parts.next().is_none(), "trailing data in line: {line:?}");
+        Self { dir, step, color }
+    }
+
+    /// Returns the direction and step of this instruction, according to the given interpretation.
+    /// Returns [None] if the color encodes an unknown direction.
+    pub fn decode(&self, interpretation: Interpretation) -> Option<PlanEntry> {
+        match interpretation {
+            Interpretation::Written => Some(PlanEntry {
+                dir: self.dir,
+                step: self.step,
+            }),
+            Interpretation::Color => Some(PlanEntry {
+                dir: Direction::from_digit(self.color.0 & 0xF)?,
+                step: (self.color.0 >> 4) as i64,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DigPlan(pub Vec<DigInstruction>);
+
+impl DigPlan {
+    pub fn parse(text: &str) -> Self {
+        Self(
+            text.lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(DigInstruction::parse)
+                .collect(),
+        )
+    }
+
+    /// Decodes the whole plan according to the given interpretation.
+    pub fn decode(&self, interpretation: Interpretation) -> Result<Vec<PlanEntry>, PlanError> {
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(i, instruction)| {
+                instruction
+                    .decode(interpretation)
+                    .ok_or(PlanError::InvalidColorDirection(i))
+            })
+            .collect()
+    }
+
+    /// Digs the trench according to the given interpretation of the plan.
+    pub fn trench(&self, interpretation: Interpretation) -> Result<Trench, PlanError> {
+        Trench::digged(&self.decode(interpretation)?)
+    }
+
+    /// Renders the trench outline, with every segment painted in the color of its instruction,
+    /// on a white background. Each pixel covers a square of `scale` x `scale` cells,
+    /// where `scale` is picked so that the image is no larger than `max_size` pixels in each
+    /// dimension.
+    pub fn render(
+        &self,
+        interpretation: Interpretation,
+        max_size: usize,
+    ) -> Result<Image, PlanError> {
+        let entries = self.decode(interpretation)?;
+        let trench = Trench::digged(&entries)?;
+
+        let height = (trench.bottom - trench.top) as usize + 1;
+        let width = (trench.right - trench.left) as usize + 1;
+        let scale = height.max(width).div_ceil(max_size.max(1)).max(1);
+        let mut image = Image::new(width.div_ceil(scale), height.div_ceil(scale));
+
+        let to_pixel = |pt: Coords| {
+            (
+                (pt.1 - trench.left) as usize / scale,
+                (pt.0 - trench.top) as usize / scale,
+            )
+        };
+
+        let mut pt = Coords(0, 0);
+        for (entry, instruction) in entries.iter().zip(&self.0) {
+            let end = pt.moved(entry.dir, entry.step);
+            let (x1, y1) = to_pixel(pt);
+            let (x2, y2) = to_pixel(end);
+            for y in y1.min(y2)..=y1.max(y2) {
+                for x in x1.min(x2)..=x1.max(x2) {
+                    image.set(x, y, instruction.color);
+                }
+            }
+            pt = end;
+        }
+
+        Ok(image)
+    }
+}
+
+pub fn load_input() -> DigPlan {
+    let mut text = String::default();
+    stdin()
+        .read_to_string(&mut text)
+        .expect("failed to read from stdin");
+    DigPlan::parse(&text)
+}
+
+/// Simple RGB raster image, which can be saved in the binary PPM format.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color(0xFFFFFF); width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    /// Writes the image in the binary PPM (P6) format.
+    pub fn write_ppm<W: Write>(&self, mut w: W) -> std::io::Result<()> {
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+        let bytes: Vec<u8> = self.pixels.iter().flat_map(|c| c.rgb()).collect();
+        w.write_all(&bytes)
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct Trench {
     pub corners: Vec<Coords>,
-    pub top: i32,
-    pub right: i32,
-    pub bottom: i32,
-    pub left: i32,
+    pub top: i64,
+    pub right: i64,
+    pub bottom: i64,
+    pub left: i64,
 }
 
 impl Trench {
-    pub fn dig(&mut self, plan: &[PlanEntry]) {
+    /// Digs the trench along the plan, which must end where it starts.
+    /// If it doesn't, an error is returned and the trench is left untouched.
+    pub fn dig(&mut self, plan: &[PlanEntry]) -> Result<(), PlanError> {
         let start = Coords(0, 0);
         let mut pt = start;
+        let mut corners = Vec::with_capacity(plan.len());
+        let (mut top, mut right, mut bottom, mut left) =
+            (self.top, self.right, self.bottom, self.left);
 
         for i in plan {
-            self.corners.push(pt);
-            self.top = self.top.min(pt.0);
-            self.bottom = self.bottom.max(pt.0);
-            self.left = self.left.min(pt.1);
-            self.right = self.right.max(pt.1);
+            corners.push(pt);
+            top = top.min(pt.0);
+            bottom = bottom.max(pt.0);
+            left = left.min(pt.1);
+            right = right.max(pt.1);
 
             pt = pt.moved(i.dir, i.step);
         }
 
-        assert!(pt == start);
+        if pt != start {
+            return Err(PlanError::NotClosed(pt));
+        }
+
+        self.corners.append(&mut corners);
         self.corners.reverse();
+        (self.top, self.right, self.bottom, self.left) = (top, right, bottom, left);
+        Ok(())
     }
 
-    pub fn digged(plan: &[PlanEntry]) -> Self {
+    pub fn digged(plan: &[PlanEntry]) -> Result<Self, PlanError> {
         let mut t = Self::default();
-        t.dig(plan);
-        t
+        t.dig(plan)?;
+        Ok(t)
+    }
+
+    /// Returns the area of the polygon spanned by the corners (going through the centers
+    /// of the trench cells), calculated with the shoelace formula.
+    pub fn area(&self) -> i64 {
+        // https://en.wikipedia.org/wiki/Shoelace_formula
+        let doubled_area = self
+            .corners
+            .iter()
+            .zip(self.corners.iter().cycle().skip(1))
+            .map(|(a, b)| a.0 as i128 * b.1 as i128 - b.0 as i128 * a.1 as i128)
+            .sum::<i128>()
+            .abs();
+        (doubled_area / 2) as i64
+    }
+
+    /// Returns the number of cells of the trench itself.
+    pub fn boundary_len(&self) -> i64 {
+        self.corners
+            .iter()
+            .zip(self.corners.iter().cycle().skip(1))
+            .map(|(a, b)| (a.0 - b.0).abs() + (a.1 - b.1).abs())
+            .sum()
+    }
+
+    /// Returns the number of cells enclosed by the trench, excluding the trench itself.
+    pub fn interior_points(&self) -> i64 {
+        // https://en.wikipedia.org/wiki/Pick%27s_theorem: A = i + b/2 - 1
+        self.area() - self.boundary_len() / 2 + 1
+    }
+
+    /// Returns the number of cells of the lagoon - the trench and the cells enclosed by it.
+    pub fn volume(&self) -> i64 {
+        self.interior_points() + self.boundary_len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "R 6 (#70c710)
+D 5 (#0dc571)
+L 2 (#5713f0)
+D 2 (#d2c081)
+R 2 (#59c680)
+D 2 (#411b91)
+L 5 (#8ceee2)
+U 2 (#caa173)
+L 1 (#1b58a2)
+U 2 (#caa171)
+R 2 (#7807d2)
+U 3 (#a77fa3)
+L 2 (#015232)
+U 2 (#7a21e3)
+";
+
+    fn square(side: i64) -> Vec<PlanEntry> {
+        [
+            Direction::Right,
+            Direction::Down,
+            Direction::Left,
+            Direction::Up,
+        ]
+        .into_iter()
+        .map(|dir| PlanEntry { dir, step: side })
+        .collect()
+    }
+
+    #[test]
+    fn dig_sample() {
+        let plan = DigPlan::parse(SAMPLE);
+        let trench = plan.trench(Interpretation::Written).unwrap();
+        assert_eq!(trench.volume(), 62);
+        let trench = plan.trench(Interpretation::Color).unwrap();
+        assert_eq!(trench.volume(), 952408144115);
+
+        let trench = Trench::digged(&square(2)).unwrap();
+        assert_eq!(trench.volume(), 9);
+        assert_eq!(
+            (trench.top, trench.right, trench.bottom, trench.left),
+            (0, 2, 2, 0)
+        );
+    }
+
+    #[test]
+    fn volume_sample() {
+        let plan = DigPlan::parse(SAMPLE);
+
+        let trench = Trench::digged(&plan.decode(Interpretation::Written).unwrap()).unwrap();
+        assert_eq!(trench.area(), 42);
+        assert_eq!(trench.boundary_len(), 38);
+        assert_eq!(trench.interior_points(), 24);
+        assert_eq!(trench.volume(), 62);
+
+        let trench = Trench::digged(&plan.decode(Interpretation::Color).unwrap()).unwrap();
+        assert_eq!(trench.volume(), 952408144115);
+        assert!(Coords(0, 461937 / 2).is_inside(&trench.corners));
+    }
+
+    #[test]
+    fn volume_large_plan() {
+        // Corners don't fit in an i32
+        let side = 3_000_000_000;
+        let trench = Trench::digged(&square(side)).unwrap();
+        assert_eq!((trench.bottom, trench.right), (side, side));
+        assert_eq!(trench.boundary_len(), 4 * side);
+        assert_eq!(trench.volume(), (side + 1) * (side + 1));
+        assert!(Coords(side / 2, side - 1).is_inside(&trench.corners));
+        assert!(!Coords(side / 2, side + 1).is_inside(&trench.corners));
+    }
+
+    #[test]
+    fn dig_not_closed() {
+        let mut trench = Trench::digged(&square(2)).unwrap();
+        let corners = trench.corners.clone();
+
+        let mut open = square(5);
+        open.pop();
+        assert_eq!(trench.dig(&open), Err(PlanError::NotClosed(Coords(5, 0))));
+
+        assert_eq!(trench.corners, corners);
+        assert_eq!(
+            (trench.top, trench.right, trench.bottom, trench.left),
+            (0, 2, 2, 0)
+        );
     }
 }
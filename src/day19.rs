@@ -1,103 +1,411 @@
 // Copyright (c) 2023 Mikołaj Kuranowski
 // SPDX-License-Identifier: MIT
 
-use std::{collections::HashMap, io::stdin, ops::Range};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::io::{stdin, Read};
+use std::ops::Range;
 
-#[derive(Debug, Clone, Copy, Default)]
-pub struct Part {
-    pub x: u16,
-    pub m: u16,
-    pub a: u16,
-    pub s: u16,
+pub type Level = u64;
+
+/// Index of an attribute (like "x", "m", "a" or "s") in a [Schema].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Value(pub usize);
+
+/// Schema maps attribute names to [Value] indices, in the order of their first appearance.
+///
+/// [parse_input] first adds the attributes in the order in which parts declare them,
+/// and only then the attributes used solely by the workflows (visiting workflows
+/// sorted by their names), so that the order doesn't depend on the order of the workflows.
+#[derive(Debug, Clone, Default)]
+pub struct Schema(Vec<String>);
+
+impl Schema {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn find(&self, name: &str) -> Option<Value> {
+        self.0.iter().position(|n| n == name).map(Value)
+    }
+
+    pub fn name(&self, v: Value) -> &str {
+        &self.0[v.0]
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = Value> {
+        (0..self.0.len()).map(Value)
+    }
+
+    pub fn intern(&mut self, name: &str) -> Value {
+        if let Some(v) = self.find(name) {
+            v
+        } else {
+            self.0.push(name.to_string());
+            Value(self.0.len() - 1)
+        }
+    }
 }
 
+/// WithSchema pairs an object with the [Schema] necessary to print attribute names.
+#[derive(Debug, Clone, Copy)]
+pub struct WithSchema<'a, T>(pub &'a T, pub &'a Schema);
+
+/// Part holds a level for every attribute of a [Schema]; attributes missing from
+/// the part are treated as 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Part(pub Vec<Level>);
+
 impl Part {
-    pub fn get(self, v: Value) -> u16 {
-        match v {
-            Value::X => self.x,
-            Value::M => self.m,
-            Value::A => self.a,
-            Value::S => self.s,
-        }
+    pub fn get(&self, v: Value) -> Level {
+        self.0.get(v.0).copied().unwrap_or(0)
     }
 
-    pub fn sum(self) -> usize {
-        self.x as usize + self.m as usize + self.a as usize + self.s as usize
+    /// Returns the sum of all levels of the part.
+    ///
+    /// Panics if the sum doesn't fit in an [u128], which would require more than 2^64 attributes.
+    pub fn sum(&self) -> u128 {
+        self.0
+            .iter()
+            .map(|&level| level as u128)
+            .try_fold(0, u128::checked_add)
+            .expect("sum of part levels doesn't fit in an u128")
     }
 
-    fn parse(x: &str) -> Self {
-        let mut p = Part::default();
+    pub fn display<'a>(&'a self, schema: &'a Schema) -> WithSchema<'a, Self> {
+        WithSchema(self, schema)
+    }
+
+    pub fn parse(x: &str, schema: &mut Schema) -> Self {
+        let mut p = Part(vec![0; schema.len()]);
         let x = &x[1..x.len() - 1]; // get rid of enclosing '{' and '}'
         for part in x.split(',') {
-            let (value_letter, level) = part.split_once('=').unwrap();
-            let level = u16::from_str_radix(level, 10).unwrap();
-            match value_letter {
-                "x" => p.x = level,
-                "m" => p.m = level,
-                "a" => p.a = level,
-                "s" => p.s = level,
-                _ => panic!("invalid part {x:?}"),
+            let (name, level) = part
+                .split_once('=')
+                .unwrap_or_else(|| panic!("invalid part {x:?}"));
+            let v = schema.intern(name);
+            if p.0.len() <= v.0 {
+                p.0.resize(v.0 + 1, 0);
             }
+            p.0[v.0] = level.parse().unwrap();
         }
         p
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct PartRange {
-    pub x: Range<u16>,
-    pub m: Range<u16>,
-    pub a: Range<u16>,
-    pub s: Range<u16>,
+/// Prints all attributes of the schema, in the schema order, e.g. `{x=787,m=2655,a=1222,s=2876}`.
+impl Display for WithSchema<'_, Part> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let WithSchema(part, schema) = self;
+        f.write_str("{")?;
+        for v in schema.values() {
+            if v.0 > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}={}", schema.name(v), part.get(v))?;
+        }
+        f.write_str("}")
+    }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartRange(pub Vec<Range<Level>>);
+
 impl PartRange {
-    pub fn get(&self, v: Value) -> Range<u16> {
-        match v {
-            Value::X => self.x.clone(),
-            Value::M => self.m.clone(),
-            Value::A => self.a.clone(),
-            Value::S => self.s.clone(),
-        }
+    /// Creates a PartRange spanning the same `levels` for every attribute of the schema.
+    pub fn uniform(schema: &Schema, levels: Range<Level>) -> Self {
+        Self(vec![levels; schema.len()])
+    }
+
+    pub fn get(&self, v: Value) -> Range<Level> {
+        self.0[v.0].clone()
     }
 
-    pub fn with(&self, v: Value, range: Range<u16>) -> Self {
+    pub fn with(&self, v: Value, range: Range<Level>) -> Self {
         let mut copy = self.clone();
-        match v {
-            Value::X => copy.x = range,
-            Value::M => copy.m = range,
-            Value::A => copy.a = range,
-            Value::S => copy.s = range,
-        };
+        copy.0[v.0] = range;
         copy
     }
 
-    pub fn total(&self) -> usize {
-        self.x.len() * self.m.len() * self.a.len() * self.s.len()
+    /// Returns true if the range doesn't contain any parts.
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().any(|r| r.is_empty())
+    }
+
+    /// Returns the number of distinct parts in the range.
+    ///
+    /// Panics if the number doesn't fit in an [u128], which can only happen
+    /// with more than 2 attributes spanning (almost) all of the [Level]s.
+    pub fn total(&self) -> u128 {
+        self.0
+            .iter()
+            .map(|r| r.end.saturating_sub(r.start) as u128)
+            .try_fold(1, u128::checked_mul)
+            .expect("number of parts in a range doesn't fit in an u128")
+    }
+
+    /// Reduces the number of ranges on a best-effort basis, by repeatedly merging adjacent ranges
+    /// which differ only in a single attribute. Empty ranges are removed.
+    ///
+    /// No two of the returned ranges can be merged into a single range, but the result
+    /// may still be larger than the smallest possible set of ranges covering the same parts,
+    /// see [PartRange::merge_minimal].
+    pub fn merge_adjacent(ranges: Vec<PartRange>) -> Vec<PartRange> {
+        let mut ranges: Vec<PartRange> = ranges.into_iter().filter(|r| !r.is_empty()).collect();
+        let attributes = ranges.first().map_or(0, |r| r.0.len());
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for merge_on in 0..attributes {
+                // Sort the ranges, so that ranges differing only on `merge_on` become neighbors
+                ranges.sort_by(|a, b| {
+                    let others = (0..attributes).filter(|&i| i != merge_on);
+                    others
+                        .chain([merge_on])
+                        .map(|i| (a.0[i].start, a.0[i].end).cmp(&(b.0[i].start, b.0[i].end)))
+                        .find(|o| o.is_ne())
+                        .unwrap_or(Ordering::Equal)
+                });
+
+                let mut merged: Vec<PartRange> = Vec::with_capacity(ranges.len());
+                for range in ranges {
+                    match merged.last_mut() {
+                        Some(last) if last.can_extend(&range, merge_on) => {
+                            last.0[merge_on].end = range.0[merge_on].end;
+                            changed = true;
+                        }
+                        _ => merged.push(range),
+                    }
+                }
+                ranges = merged;
+            }
+        }
+
+        ranges
+    }
+
+    /// Merges the ranges into the smallest set of disjoint ranges covering the same parts,
+    /// which can be obtained by recursively cutting a range in two along a single attribute -
+    /// just like rules of a [Workflow] split ranges. Empty ranges are removed.
+    ///
+    /// All sub-ranges between the start and end levels of the provided ranges are considered,
+    /// so this is only practical for ranges with a few distinct levels per attribute.
+    /// [PartRange::merge_adjacent] is much faster, but doesn't guarantee a minimal result.
+    pub fn merge_minimal(ranges: Vec<PartRange>) -> Vec<PartRange> {
+        let ranges: Vec<PartRange> = ranges.into_iter().filter(|r| !r.is_empty()).collect();
+        if ranges.is_empty() {
+            return Vec::default();
+        }
+
+        let mut merge = MinimalMerge::new(ranges);
+        let whole: Vec<Range<usize>> = merge.levels.iter().map(|l| 0..l.len() - 1).collect();
+        merge.solve(&whole);
+
+        let mut result = Vec::default();
+        merge.collect(&whole, &mut result);
+        result
+    }
+
+    fn can_extend(&self, other: &PartRange, on: usize) -> bool {
+        self.0[on].end == other.0[on].start
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .enumerate()
+                .all(|(i, (a, b))| i == on || a == b)
     }
 }
 
+/// Best way to cover a sub-range with disjoint ranges, found by [MinimalMerge].
 #[derive(Debug, Clone, Copy)]
-pub enum Value {
-    X,
-    M,
-    A,
-    S,
+enum Cover {
+    Empty,
+    Full,
+    Cut { count: usize, on: usize, at: usize },
 }
 
-impl Value {
-    fn parse(x: &str) -> Self {
-        match x {
-            "x" => Self::X,
-            "m" => Self::M,
-            "a" => Self::A,
-            "s" => Self::S,
-            _ => panic!("invalid value {:?}", x),
+impl Cover {
+    fn count(self) -> usize {
+        match self {
+            Self::Empty => 0,
+            Self::Full => 1,
+            Self::Cut { count, .. } => count,
         }
     }
 }
 
-#[derive(Debug)]
+/// MinimalMerge implements [PartRange::merge_minimal]. Sub-ranges are described by indices
+/// into `levels`, the sorted start and end levels of the merged ranges of every attribute.
+struct MinimalMerge {
+    ranges: Vec<PartRange>,
+    levels: Vec<Vec<Level>>,
+    solved: HashMap<Vec<Range<usize>>, Cover>,
+}
+
+impl MinimalMerge {
+    fn new(ranges: Vec<PartRange>) -> Self {
+        let levels = (0..ranges[0].0.len())
+            .map(|i| {
+                let mut levels: Vec<Level> = ranges
+                    .iter()
+                    .flat_map(|r| [r.0[i].start, r.0[i].end])
+                    .collect();
+                levels.sort_unstable();
+                levels.dedup();
+                levels
+            })
+            .collect();
+
+        Self {
+            ranges,
+            levels,
+            solved: HashMap::default(),
+        }
+    }
+
+    fn solve(&mut self, sub: &[Range<usize>]) -> Cover {
+        if let Some(&cover) = self.solved.get(sub) {
+            return cover;
+        }
+
+        let cuts: Vec<(usize, usize)> = sub
+            .iter()
+            .enumerate()
+            .flat_map(|(on, r)| (r.start + 1..r.end).map(move |at| (on, at)))
+            .collect();
+
+        let cover = if cuts.is_empty() {
+            // A single cell - it's either entirely inside one of the ranges, or outside all of them
+            let corner: Vec<Level> = sub
+                .iter()
+                .zip(&self.levels)
+                .map(|(r, levels)| levels[r.start])
+                .collect();
+            let covered = self
+                .ranges
+                .iter()
+                .any(|range| range.0.iter().zip(&corner).all(|(r, l)| r.contains(l)));
+            if covered {
+                Cover::Full
+            } else {
+                Cover::Empty
+            }
+        } else {
+            let mut best: Option<Cover> = None;
+            for (on, at) in cuts {
+                let (low, high) = split_at(sub, on, at);
+                let (low, high) = (self.solve(&low), self.solve(&high));
+                let count = low.count() + high.count();
+
+                match (best, low, high) {
+                    // Whether the sub-range is entirely empty or full is known after the first cut
+                    (None, Cover::Empty, Cover::Empty) => {
+                        best = Some(Cover::Empty);
+                        break;
+                    }
+                    (None, Cover::Full, Cover::Full) => {
+                        best = Some(Cover::Full);
+                        break;
+                    }
+                    (Some(b), _, _) if b.count() <= count => {}
+                    _ => best = Some(Cover::Cut { count, on, at }),
+                }
+            }
+            best.unwrap()
+        };
+
+        self.solved.insert(sub.to_vec(), cover);
+        cover
+    }
+
+    fn collect(&self, sub: &[Range<usize>], result: &mut Vec<PartRange>) {
+        match self.solved[sub] {
+            Cover::Empty => {}
+            Cover::Full => result.push(PartRange(
+                sub.iter()
+                    .zip(&self.levels)
+                    .map(|(r, levels)| levels[r.start]..levels[r.end])
+                    .collect(),
+            )),
+            Cover::Cut { on, at, .. } => {
+                let (low, high) = split_at(sub, on, at);
+                self.collect(&low, result);
+                self.collect(&high, result);
+            }
+        }
+    }
+}
+
+/// Splits index ranges of a sub-range into two at index `at` of the `on` attribute.
+fn split_at(sub: &[Range<usize>], on: usize, at: usize) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+    let mut low = sub.to_vec();
+    let mut high = sub.to_vec();
+    low[on].end = at;
+    high[on].start = at;
+    (low, high)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operator {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Operator {
+    pub fn test(self, level: Level, cutoff: Level) -> bool {
+        match self {
+            Self::Less => level < cutoff,
+            Self::LessEqual => level <= cutoff,
+            Self::Greater => level > cutoff,
+            Self::GreaterEqual => level >= cutoff,
+            Self::Equal => level == cutoff,
+            Self::NotEqual => level != cutoff,
+        }
+    }
+
+    /// Splits off an operator from the start of `x`, returning the operator and the remaining text.
+    fn parse_prefix(x: &str) -> Option<(Self, &str)> {
+        // Two-character operators must be checked first, so that "<=" isn't mistaken for "<"
+        const OPERATORS: [(&str, Operator); 6] = [
+            ("<=", Operator::LessEqual),
+            (">=", Operator::GreaterEqual),
+            ("==", Operator::Equal),
+            ("!=", Operator::NotEqual),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+        ];
+        OPERATORS
+            .iter()
+            .find_map(|&(symbol, op)| x.strip_prefix(symbol).map(|rest| (op, rest)))
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Less => "<",
+            Self::LessEqual => "<=",
+            Self::Greater => ">",
+            Self::GreaterEqual => ">=",
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Reference {
     Accept,
     Reject,
@@ -114,65 +422,103 @@ impl Reference {
     }
 }
 
+impl Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Accept => f.write_str("A"),
+            Self::Reject => f.write_str("R"),
+            Self::To(name) => f.write_str(name),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Condition {
     Unconditional,
-    LessThan(Value, u16),
-    GreaterThan(Value, u16),
+    Compare(Value, Operator, Level),
 }
 
 impl Condition {
-    pub fn partition(&self, range: PartRange) -> (Option<PartRange>, Option<PartRange>) {
-        match self {
-            Self::Unconditional => (Some(range), None),
-            Self::LessThan(v, cutoff) => {
-                let current = range.get(*v);
-                if current.contains(cutoff) {
-                    (
-                        Some(range.clone().with(*v, current.start..*cutoff)),
-                        Some(range.with(*v, *cutoff..current.end)),
-                    )
-                } else if cutoff < &current.start {
-                    (None, Some(range))
-                } else {
-                    // cutoff >= current.end
-                    (Some(range), None)
-                }
-            }
-            Self::GreaterThan(v, cutoff) => {
-                let current = range.get(*v);
-                if current.contains(cutoff) {
-                    (
-                        Some(range.clone().with(*v, *cutoff + 1..current.end)),
-                        Some(range.with(*v, current.start..*cutoff + 1)),
-                    )
-                } else if cutoff < &current.start {
-                    (Some(range), None)
-                } else {
-                    // cutoff >= current.end
-                    (None, Some(range))
-                }
+    /// Splits the range into sub-ranges conforming and non-conforming to the condition.
+    /// Each of the returned lists contains at most 2 elements.
+    pub fn partition(&self, range: PartRange) -> (Vec<PartRange>, Vec<PartRange>) {
+        let (v, op, cutoff) = match self {
+            Self::Unconditional => return (vec![range], Vec::default()),
+            Self::Compare(v, op, cutoff) => (*v, *op, *cutoff),
+        };
+
+        // Split the current range at `cutoff` and `cutoff + 1` - every operator gives
+        // the same answer for all levels within each of the pieces.
+        let current = range.get(v);
+        let after_cutoff = cutoff.saturating_add(1);
+        let pieces = [
+            current.start..cutoff.min(current.end),
+            cutoff.max(current.start)..after_cutoff.min(current.end),
+            after_cutoff.max(current.start)..current.end,
+        ];
+
+        let mut conforming: Vec<Range<Level>> = Vec::default();
+        let mut non_conforming: Vec<Range<Level>> = Vec::default();
+        for piece in pieces.into_iter().filter(|r| !r.is_empty()) {
+            let target = if op.test(piece.start, cutoff) {
+                &mut conforming
+            } else {
+                &mut non_conforming
+            };
+
+            match target.last_mut() {
+                Some(last) if last.end == piece.start => last.end = piece.end,
+                _ => target.push(piece),
             }
         }
+
+        (
+            conforming.into_iter().map(|r| range.with(v, r)).collect(),
+            non_conforming
+                .into_iter()
+                .map(|r| range.with(v, r))
+                .collect(),
+        )
     }
-}
 
-impl Condition {
     pub fn test(&self, part: &Part) -> bool {
         match self {
             Self::Unconditional => true,
-            Self::GreaterThan(value, cutoff) => part.get(*value) > *cutoff,
-            Self::LessThan(value, cutoff) => part.get(*value) < *cutoff,
+            Self::Compare(value, op, cutoff) => op.test(part.get(*value), *cutoff),
         }
     }
 
-    fn parse(x: &str) -> Self {
-        let value = Value::parse(&x[0..1]);
-        let cutoff = u16::from_str_radix(&x[2..], 10).unwrap();
-        match &x[1..2] {
-            "<" => Self::LessThan(value, cutoff),
-            ">" => Self::GreaterThan(value, cutoff),
-            _ => panic!("invalid condition: {:?}", x),
+    /// Compares two conditions, possibly coming from systems with different schemas.
+    /// Attributes are compared by their names, not by their indices.
+    pub fn eq_with(&self, schema: &Schema, other: &Self, other_schema: &Schema) -> bool {
+        match (self, other) {
+            (Self::Unconditional, Self::Unconditional) => true,
+            (Self::Compare(v, op, cutoff), Self::Compare(other_v, other_op, other_cutoff)) => {
+                schema.name(*v) == other_schema.name(*other_v)
+                    && op == other_op
+                    && cutoff == other_cutoff
+            }
+            _ => false,
+        }
+    }
+
+    fn parse(x: &str, schema: &mut Schema) -> Self {
+        let name_end = x
+            .find(['<', '>', '=', '!'])
+            .unwrap_or_else(|| panic!("invalid condition: {x:?}"));
+        let (name, rest) = x.split_at(name_end);
+        let (op, cutoff) =
+            Operator::parse_prefix(rest).unwrap_or_else(|| panic!("invalid condition: {x:?}"));
+        Self::Compare(schema.intern(name), op, cutoff.parse().unwrap())
+    }
+}
+
+/// Prints the condition without the trailing colon; unconditional conditions print nothing.
+impl Display for WithSchema<'_, Condition> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Condition::Unconditional => Ok(()),
+            Condition::Compare(v, op, cutoff) => write!(f, "{}{}{}", self.1.name(*v), op, cutoff),
         }
     }
 }
@@ -185,18 +531,22 @@ pub struct Rule {
 
 impl Rule {
     pub fn applies(&self, part: &Part) -> Option<&Reference> {
-        if self.condition.test(&part) {
+        if self.condition.test(part) {
             Some(&self.reference)
         } else {
             None
         }
     }
 
-    fn parse(x: &str) -> Self {
+    pub fn display<'a>(&'a self, schema: &'a Schema) -> WithSchema<'a, Self> {
+        WithSchema(self, schema)
+    }
+
+    fn parse(x: &str, schema: &mut Schema) -> Self {
         let (condition, reference) = if let Some((condition_str, reference_str)) = x.split_once(':')
         {
             (
-                Condition::parse(condition_str),
+                Condition::parse(condition_str, schema),
                 Reference::parse(reference_str),
             )
         } else {
@@ -209,32 +559,58 @@ impl Rule {
     }
 }
 
+impl Display for WithSchema<'_, Rule> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let WithSchema(rule, schema) = self;
+        match rule.condition {
+            Condition::Unconditional => write!(f, "{}", rule.reference),
+            _ => write!(
+                f,
+                "{}:{}",
+                WithSchema(&rule.condition, schema),
+                rule.reference
+            ),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Workflow(Vec<Rule>);
 
 impl Workflow {
+    pub fn rules(&self) -> &[Rule] {
+        &self.0
+    }
+
     pub fn apply(&self, part: &Part) -> &Reference {
-        for rule in &self.0 {
-            if let Some(reference) = rule.applies(part) {
-                return reference;
-            }
-        }
-        panic!("couldn't match a part to a workflow - missing unconditional rule?");
+        &self.0[self.matching_rule(part)].reference
+    }
+
+    /// Returns the index of the first rule which applies to the part.
+    pub fn matching_rule(&self, part: &Part) -> usize {
+        self.0
+            .iter()
+            .position(|rule| rule.applies(part).is_some())
+            .expect("couldn't match a part to a workflow - missing unconditional rule?")
     }
 
-    pub fn apply_range(&self, mut range: PartRange) -> Vec<(PartRange, &Reference)> {
+    /// Splits the range into sub-ranges, each paired with the index of the first rule
+    /// which applies to it.
+    pub fn apply_range(&self, range: PartRange) -> Vec<(PartRange, usize)> {
         let mut result = Vec::default();
+        let mut remaining = vec![range];
 
-        for rule in &self.0 {
-            let (conforming, non_conforming) = rule.condition.partition(range);
+        for (idx, rule) in self.0.iter().enumerate() {
+            let mut non_conforming_ranges = Vec::default();
 
-            if let Some(conforming) = conforming {
-                result.push((conforming, &rule.reference));
+            for range in remaining {
+                let (conforming, mut non_conforming) = rule.condition.partition(range);
+                result.extend(conforming.into_iter().map(|r| (r, idx)));
+                non_conforming_ranges.append(&mut non_conforming);
             }
 
-            if let Some(non_conforming) = non_conforming {
-                range = non_conforming;
-            } else {
+            remaining = non_conforming_ranges;
+            if remaining.is_empty() {
                 break;
             }
         }
@@ -242,20 +618,94 @@ impl Workflow {
         result
     }
 
-    fn parse(x: &str) -> Self {
+    pub fn display<'a>(&'a self, schema: &'a Schema) -> WithSchema<'a, Self> {
+        WithSchema(self, schema)
+    }
+
+    fn eq_with(&self, schema: &Schema, other: &Self, other_schema: &Schema) -> bool {
+        self.0.len() == other.0.len()
+            && self.0.iter().zip(&other.0).all(|(a, b)| {
+                a.reference == b.reference
+                    && a.condition.eq_with(schema, &b.condition, other_schema)
+            })
+    }
+
+    fn parse(x: &str, schema: &mut Schema) -> Self {
         let x = &x[1..x.len() - 1]; // get rid of enclosing '{' and '}'
-        Self(x.split(',').map(Rule::parse).collect())
+        Self(x.split(',').map(|r| Rule::parse(r, schema)).collect())
+    }
+}
+
+/// Prints the rules of a workflow, enclosed in '{' and '}', without the workflow's name.
+impl Display for WithSchema<'_, Workflow> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let WithSchema(workflow, schema) = self;
+        f.write_str("{")?;
+        for (i, rule) in workflow.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", rule.display(schema))?;
+        }
+        f.write_str("}")
     }
 }
 
+/// Step describes a single rule tested while routing parts through a [System].
+#[derive(Debug, Clone, Copy)]
+pub struct Step<'a> {
+    pub workflow: &'a str,
+    pub index: usize,
+    pub rule: &'a Rule,
+    pub matched: bool,
+}
+
+/// AcceptedRegion is a range of parts accepted by a [System],
+/// together with the rules which have sent those parts to acceptance.
+#[derive(Debug, Clone)]
+pub struct AcceptedRegion<'a> {
+    pub range: PartRange,
+    pub path: Vec<Step<'a>>,
+}
+
+/// Explanation lists every rule tested while routing a single part through a [System].
+#[derive(Debug, Clone)]
+pub struct Explanation<'a> {
+    pub steps: Vec<Step<'a>>,
+    pub accepted: bool,
+}
+
 #[derive(Debug, Default)]
-pub struct System(HashMap<String, Workflow>);
+pub struct System {
+    workflows: HashMap<String, Workflow>,
+    schema: Schema,
+}
 
 impl System {
+    /// Parses a system from its textual form, ignoring any parts after the workflows.
+    pub fn parse(text: &str) -> Self {
+        parse_input(text).0
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn workflow(&self, name: &str) -> Option<&Workflow> {
+        self.workflows.get(name)
+    }
+
+    /// Returns the names of all workflows, sorted alphabetically.
+    pub fn workflow_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.workflows.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
     pub fn is_accepted(&self, part: &Part) -> bool {
         let mut to_apply: &str = "in";
         loop {
-            match self.0.get(to_apply).unwrap().apply(part) {
+            match self.workflows.get(to_apply).unwrap().apply(part) {
                 Reference::Accept => return true,
                 Reference::Reject => return false,
                 Reference::To(next_name) => to_apply = next_name,
@@ -263,20 +713,94 @@ impl System {
         }
     }
 
-    pub fn count_accepted(&self, initial: PartRange) -> usize {
-        let mut result = 0;
-        let initial_reference = Reference::To("in".to_string());
-        let mut candidates = vec![(initial, &initial_reference)];
+    pub fn explain(&self, part: &Part) -> Explanation<'_> {
+        let mut steps = Vec::default();
+        let mut to_apply: &str = "in";
+        loop {
+            let (workflow, w) = self.workflows.get_key_value(to_apply).unwrap();
+            let matched_idx = w.matching_rule(part);
 
-        while let Some((range, reference)) = candidates.pop() {
-            match reference {
+            steps.extend(
+                w.0[..=matched_idx]
+                    .iter()
+                    .enumerate()
+                    .map(|(index, rule)| Step {
+                        workflow,
+                        index,
+                        rule,
+                        matched: index == matched_idx,
+                    }),
+            );
+
+            match &w.0[matched_idx].reference {
                 Reference::Accept => {
-                    result += range.total();
+                    return Explanation {
+                        steps,
+                        accepted: true,
+                    }
+                }
+                Reference::Reject => {
+                    return Explanation {
+                        steps,
+                        accepted: false,
+                    }
                 }
-                Reference::Reject => {}
-                Reference::To(next) => {
-                    let w = self.0.get(next).unwrap();
-                    candidates.append(&mut w.apply_range(range));
+                Reference::To(next_name) => to_apply = next_name,
+            }
+        }
+    }
+
+    /// Returns the number of parts (within `initial`) accepted by the system.
+    ///
+    /// Panics if the number doesn't fit in an [u128].
+    pub fn count_accepted(&self, initial: PartRange) -> u128 {
+        // Same walk as in accepted_regions, but without keeping track of the paths
+        let mut total: u128 = 0;
+        let mut candidates: Vec<(PartRange, &str)> = vec![(initial, "in")];
+
+        while let Some((range, to_apply)) = candidates.pop() {
+            let w = self.workflows.get(to_apply).unwrap();
+
+            for (range, index) in w.apply_range(range) {
+                match &w.0[index].reference {
+                    Reference::Accept => {
+                        total = total
+                            .checked_add(range.total())
+                            .expect("number of accepted parts doesn't fit in an u128")
+                    }
+                    Reference::Reject => {}
+                    Reference::To(next) => candidates.push((range, next)),
+                }
+            }
+        }
+
+        total
+    }
+
+    /// Returns all disjoint ranges of parts (within `initial`) accepted by the system.
+    /// Every region's path only contains the rules which have matched.
+    pub fn accepted_regions(&self, initial: PartRange) -> Vec<AcceptedRegion<'_>> {
+        let mut result = Vec::default();
+        let mut candidates: Vec<(PartRange, &str, Vec<Step>)> =
+            vec![(initial, "in", Vec::default())];
+
+        while let Some((range, to_apply, path)) = candidates.pop() {
+            let (workflow, w) = self.workflows.get_key_value(to_apply).unwrap();
+
+            for (range, index) in w.apply_range(range) {
+                let rule = &w.0[index];
+                let mut path = path.clone();
+                path.push(Step {
+                    workflow,
+                    index,
+                    rule,
+                    matched: true,
+                });
+
+                match &rule.reference {
+                    Reference::Accept => result.push(AcceptedRegion { range, path }),
+                    Reference::Reject => {}
+                    Reference::To(next) => candidates.push((range, next, path)),
                 }
             }
         }
@@ -285,23 +809,296 @@ impl System {
     }
 }
 
-pub fn load_input() -> (System, Vec<Part>) {
-    let mut system = System::default();
-    let mut parts = Vec::default();
-    let mut parsing_parts = false;
+impl PartialEq for System {
+    /// Systems are equal if they have the same workflows. Attributes are compared by name,
+    /// so the order of attributes in the schemas doesn't matter.
+    fn eq(&self, other: &Self) -> bool {
+        self.workflows.len() == other.workflows.len()
+            && self.workflows.iter().all(|(name, w)| {
+                other
+                    .workflows
+                    .get(name)
+                    .is_some_and(|o| w.eq_with(&self.schema, o, &other.schema))
+            })
+    }
+}
 
-    for line in stdin().lines() {
-        let line = line.expect("failed to read from stdin");
+impl Eq for System {}
 
-        if line.is_empty() {
-            parsing_parts = true;
-        } else if parsing_parts {
-            parts.push(Part::parse(&line));
-        } else {
-            let (name, workflow) = line.split_at(line.find('{').unwrap());
-            system.0.insert(name.to_string(), Workflow::parse(workflow));
+/// Prints every workflow on a separate line, ordered by workflow names.
+impl Display for System {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in self.workflow_names() {
+            writeln!(f, "{}{}", name, self.workflows[name].display(&self.schema))?;
         }
+        Ok(())
     }
+}
+
+pub fn parse_input(text: &str) -> (System, Vec<Part>) {
+    let mut system = System::default();
+    let mut lines = text.lines();
+    let mut workflows: Vec<(&str, &str)> = lines
+        .by_ref()
+        .take_while(|line| !line.is_empty())
+        .map(|line| line.split_at(line.find('{').unwrap()))
+        .collect();
+
+    // Parts are parsed first, so that the schema keeps the order of their attributes
+    let mut parts: Vec<Part> = lines
+        .filter(|line| !line.is_empty())
+        .map(|line| Part::parse(line, &mut system.schema))
+        .collect();
+
+    workflows.sort_by_key(|&(name, _)| name);
+    for (name, workflow) in workflows {
+        let workflow = Workflow::parse(workflow, &mut system.schema);
+        system.workflows.insert(name.to_string(), workflow);
+    }
+
+    // Attributes first seen in later parts must also be present in the earlier ones
+    let attributes = system.schema.len();
+    parts.iter_mut().for_each(|p| p.0.resize(attributes, 0));
 
     (system, parts)
 }
+
+pub fn load_input() -> (System, Vec<Part>) {
+    let mut text = String::default();
+    stdin()
+        .read_to_string(&mut text)
+        .expect("failed to read from stdin");
+    parse_input(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "px{a<2006:qkq,m>2090:A,rfg}
+pv{a>1716:R,A}
+lnx{m>1548:A,A}
+rfg{s<537:gd,x>2440:R,A}
+qs{s>3448:A,lnx}
+qkq{x<1416:A,crn}
+crn{x>2662:A,R}
+in{s<1351:px,qqz}
+qqz{s>2770:qs,m<1801:hdj,R}
+gd{a>3333:R,R}
+hdj{m>838:A,pv}
+
+{x=787,m=2655,a=1222,s=2876}
+{x=1679,m=44,a=2067,s=496}
+{x=2036,m=264,a=79,s=2244}
+{x=2461,m=1339,a=466,s=291}
+{x=2127,m=1623,a=2188,s=1013}
+";
+
+    #[test]
+    fn display_round_trip() {
+        let (system, parts) = parse_input(SAMPLE);
+
+        let printed = system.to_string();
+        assert!(printed.starts_with("crn{x>2662:A,R}\ngd{a>3333:R,R}\n"));
+
+        // Re-parsed system is equal and prints identically, even though its schema
+        // (without any parts) lists the attributes in a different order
+        let reparsed = System::parse(&printed);
+        assert_eq!(reparsed, system);
+        assert_eq!(reparsed.to_string(), printed);
+
+        let printed_parts: Vec<String> = parts
+            .iter()
+            .map(|part| part.display(system.schema()).to_string())
+            .collect();
+        assert_eq!(printed_parts[0], "{x=787,m=2655,a=1222,s=2876}");
+
+        let text = format!("{printed}\n{}\n", printed_parts.join("\n"));
+        let (reparsed, reparsed_parts) = parse_input(&text);
+        assert_eq!(reparsed, system);
+        assert_eq!(reparsed_parts, parts);
+        assert_eq!(reparsed.to_string(), printed);
+        for (part, printed) in reparsed_parts.iter().zip(&printed_parts) {
+            assert_eq!(part.display(reparsed.schema()).to_string(), *printed);
+        }
+    }
+
+    #[test]
+    fn display_ignores_workflow_order() {
+        let (workflows, parts) = SAMPLE.split_once("\n\n").unwrap();
+        let reversed: Vec<&str> = workflows.lines().rev().collect();
+        let text = format!(
+            "{}\nbb{{q>1:A,R}}\naa{{p>1:A,R}}\n\n{parts}",
+            reversed.join("\n")
+        );
+
+        let (system, parts) = parse_input(SAMPLE);
+        let (reversed, reversed_parts) = parse_input(&text);
+        let p = reversed.schema().find("p").unwrap();
+        assert_eq!(reversed.schema().find("q"), Some(Value(p.0 + 1)));
+
+        for (part, reversed_part) in parts.iter().zip(&reversed_parts) {
+            let printed = part.display(system.schema()).to_string();
+            let reversed_printed = reversed_part.display(reversed.schema()).to_string();
+            assert_eq!(
+                reversed_printed,
+                format!("{},p=0,q=0}}", &printed[..printed.len() - 1])
+            );
+        }
+    }
+
+    #[test]
+    fn count_accepted_sample() {
+        let system = System::parse(SAMPLE);
+        let initial = PartRange::uniform(system.schema(), 1..4001);
+        assert_eq!(system.count_accepted(initial.clone()), 167409079868000);
+
+        let regions: Vec<PartRange> = system
+            .accepted_regions(initial)
+            .into_iter()
+            .map(|region| region.range)
+            .collect();
+        let total: u128 = regions.iter().map(PartRange::total).sum();
+        assert_eq!(total, 167409079868000);
+
+        let merged = PartRange::merge_adjacent(regions.clone());
+        assert!(merged.len() <= regions.len());
+        assert_eq!(merged.iter().map(PartRange::total).sum::<u128>(), total);
+        for (i, a) in merged.iter().enumerate() {
+            for b in &merged[i + 1..] {
+                assert!((0..a.0.len()).all(|on| !a.can_extend(b, on) && !b.can_extend(a, on)));
+            }
+        }
+    }
+
+    /// Returns the smallest number of disjoint rectangles covering exactly the given cells,
+    /// by trying every rectangle with the first uncovered cell as its lowest corner.
+    fn brute_force_minimal(cells: &[(u64, u64)], covered: &mut Vec<(u64, u64)>) -> usize {
+        let Some(&(x, y)) = cells.iter().find(|c| !covered.contains(c)) else {
+            return 0;
+        };
+
+        let usable =
+            |c: &(u64, u64), covered: &[(u64, u64)]| cells.contains(c) && !covered.contains(c);
+        let mut rectangles: Vec<Vec<(u64, u64)>> = Vec::default();
+        for x_end in (x + 1..).take_while(|&x_end| usable(&(x_end - 1, y), covered)) {
+            for y_end in y + 1.. {
+                let rectangle: Vec<(u64, u64)> = (x..x_end)
+                    .flat_map(|cx| (y..y_end).map(move |cy| (cx, cy)))
+                    .collect();
+                if !rectangle.iter().all(|c| usable(c, covered)) {
+                    break;
+                }
+                rectangles.push(rectangle);
+            }
+        }
+
+        let mut best = usize::MAX;
+        for rectangle in rectangles {
+            covered.extend(&rectangle);
+            best = best.min(1 + brute_force_minimal(cells, covered));
+            covered.truncate(covered.len() - rectangle.len());
+        }
+        best
+    }
+
+    fn check_merged(merged: &[PartRange], ranges: &[PartRange]) {
+        let total: u128 = ranges.iter().map(PartRange::total).sum();
+        assert_eq!(merged.iter().map(PartRange::total).sum::<u128>(), total);
+
+        for (i, a) in merged.iter().enumerate() {
+            assert!(!a.is_empty());
+            for b in &merged[i + 1..] {
+                let overlap =
+                    a.0.iter()
+                        .zip(&b.0)
+                        .all(|(a, b)| a.start < b.end && b.start < a.end);
+                assert!(!overlap, "{a:?} and {b:?} overlap");
+            }
+        }
+    }
+
+    #[test]
+    fn merge_minimal_sample() {
+        let system = System::parse(SAMPLE);
+        let initial = PartRange::uniform(system.schema(), 1..4001);
+        let regions: Vec<PartRange> = system
+            .accepted_regions(initial)
+            .into_iter()
+            .map(|region| region.range)
+            .collect();
+
+        let merged = PartRange::merge_minimal(regions.clone());
+        check_merged(&merged, &regions);
+        assert!(merged.len() <= PartRange::merge_adjacent(regions).len());
+    }
+
+    #[test]
+    fn merge_minimal_beats_adjacent() {
+        // .##
+        // ###
+        // #.#
+        let cells = [(1, 0), (2, 0), (0, 1), (1, 1), (2, 1), (0, 2), (2, 2)];
+        let ranges: Vec<PartRange> = cells
+            .iter()
+            .map(|&(x, y)| PartRange(vec![x..x + 1, y..y + 1]))
+            .collect();
+
+        let merged = PartRange::merge_minimal(ranges.clone());
+        check_merged(&merged, &ranges);
+        assert_eq!(merged.len(), 3);
+        assert_eq!(PartRange::merge_adjacent(ranges).len(), 4);
+    }
+
+    #[test]
+    fn merge_minimal_brute_force() {
+        // Every subset of cells of a 3x3 grid
+        for mask in 0..512_u32 {
+            let cells: Vec<(u64, u64)> = (0..9)
+                .filter(|&i| mask & (1 << i) != 0)
+                .map(|i| ((i % 3) as u64, (i / 3) as u64))
+                .collect();
+            let ranges: Vec<PartRange> = cells
+                .iter()
+                .map(|&(x, y)| PartRange(vec![x..x + 1, y..y + 1]))
+                .collect();
+
+            let merged = PartRange::merge_minimal(ranges.clone());
+            check_merged(&merged, &ranges);
+            assert_eq!(
+                merged.len(),
+                brute_force_minimal(&cells, &mut Vec::default()),
+                "cells {cells:?}"
+            );
+        }
+        assert!(PartRange::merge_minimal(vec![PartRange(vec![5..5, 0..3])]).is_empty());
+    }
+
+    #[test]
+    fn total_wide_ranges() {
+        let range = PartRange(vec![0..Level::MAX, 0..Level::MAX]);
+        assert_eq!(range.total(), (Level::MAX as u128) * (Level::MAX as u128));
+        assert_eq!(range.with(Value(1), 5..5).total(), 0);
+        assert!(range.with(Value(1), 5..5).is_empty());
+    }
+
+    #[test]
+    fn part_sum_wide_levels() {
+        let part = Part(vec![Level::MAX, Level::MAX, 1]);
+        assert_eq!(part.sum(), 2 * (Level::MAX as u128) + 1);
+
+        let (system, parts) = parse_input(SAMPLE);
+        let accepted: u128 = parts
+            .iter()
+            .filter(|p| system.is_accepted(p))
+            .map(Part::sum)
+            .sum();
+        assert_eq!(accepted, 19114);
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't fit in an u128")]
+    fn total_overflow() {
+        PartRange(vec![0..Level::MAX; 3]).total();
+    }
+}
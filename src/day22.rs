@@ -1,19 +1,23 @@
 // Copyright (c) 2023 Mikołaj Kuranowski
 // SPDX-License-Identifier: MIT
 
+use crate::rng::XorShift;
 use std::collections::{HashMap, HashSet};
-use std::io::stdin;
+use std::fmt;
+use std::io::{stdin, Read, Write};
 use std::ops::Range;
 
+pub type Coord = u32;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-struct Cube(u16, u16, u16);
+pub struct Cube(pub Coord, pub Coord, pub Coord);
 
 impl Cube {
-    fn parse(x: &str) -> Self {
+    pub fn parse(x: &str) -> Self {
         let mut parts = x.split(',');
-        let x = u16::from_str_radix(parts.next().unwrap(), 10).unwrap();
-        let y = u16::from_str_radix(parts.next().unwrap(), 10).unwrap();
-        let z = u16::from_str_radix(parts.next().unwrap(), 10).unwrap();
+        let x = parts.next().unwrap().parse().unwrap();
+        let y = parts.next().unwrap().parse().unwrap();
+        let z = parts.next().unwrap().parse().unwrap();
         Self(x, y, z)
     }
 
@@ -22,7 +26,7 @@ impl Cube {
         let ys = l.1..=r.1;
         let zs = l.2..=r.2;
 
-        let mut result = Vec::with_capacity(xs.len() * ys.len() * zs.len());
+        let mut result = Vec::default();
         for x in xs {
             for y in ys.clone() {
                 for z in zs.clone() {
@@ -43,16 +47,553 @@ impl Cube {
 
 pub type BrickID = u16;
 
-#[derive(Debug)]
-struct Brick(Vec<Cube>);
+/// Brick represented as a segment of cubes, from `start` to `end` (inclusive).
+/// Every coordinate of `start` is not greater than the corresponding coordinate of `end`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Brick {
+    pub start: Cube,
+    pub end: Cube,
+}
 
-#[derive(Debug)]
+impl Brick {
+    pub fn new(a: Cube, b: Cube) -> Self {
+        Self {
+            start: Cube(a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)),
+            end: Cube(a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)),
+        }
+    }
+
+    pub fn parse(x: &str) -> Self {
+        let (l_str, r_str) = x.split_once('~').unwrap();
+        Self::new(Cube::parse(l_str), Cube::parse(r_str))
+    }
+
+    pub fn bottom(&self) -> Coord {
+        self.start.2
+    }
+
+    pub fn top(&self) -> Coord {
+        self.end.2
+    }
+
+    /// Returns the (x, y) coordinates of cells covered by the brick, as seen from above.
+    pub fn footprint(&self) -> impl Iterator<Item = (Coord, Coord)> {
+        let ys = self.start.1..=self.end.1;
+        (self.start.0..=self.end.0).flat_map(move |x| ys.clone().map(move |y| (x, y)))
+    }
+
+    /// Returns the same brick moved vertically, so that its bottom is at the provided level.
+    pub fn with_bottom(&self, z: Coord) -> Self {
+        let height = self.end.2 - self.start.2;
+        Self {
+            start: Cube(self.start.0, self.start.1, z),
+            end: Cube(self.end.0, self.end.1, z + height),
+        }
+    }
+}
+
+/// Describes which bricks rest directly on which. All lists are sorted by [BrickID].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SupportGraph {
+    /// `supports[a]` lists bricks which rest directly on top of brick `a`.
+    pub supports: Vec<Vec<BrickID>>,
+
+    /// `supported_by[a]` lists bricks on which brick `a` rests directly.
+    /// Empty for bricks lying on the floor.
+    pub supported_by: Vec<Vec<BrickID>>,
+}
+
+impl SupportGraph {
+    /// Creates a support graph from the lists of bricks every brick rests on.
+    pub fn from_supported_by(mut supported_by: Vec<Vec<BrickID>>) -> Self {
+        let mut supports = vec![Vec::default(); supported_by.len()];
+        for (id, below) in supported_by.iter_mut().enumerate() {
+            below.sort();
+            below.dedup();
+            for &below_id in below.iter() {
+                supports[below_id as usize].push(id as BrickID);
+            }
+        }
+        Self {
+            supports,
+            supported_by,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.supports.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.supports.is_empty()
+    }
+
+    pub fn id_range(&self) -> Range<BrickID> {
+        0..(self.len() as BrickID)
+    }
+
+    /// Returns all bricks which can be removed without causing any other brick to fall.
+    pub fn safe_to_disintegrate(&self) -> HashSet<BrickID> {
+        self.id_range()
+            .filter(|&id| {
+                self.supports[id as usize]
+                    .iter()
+                    .all(|&above| self.supported_by[above as usize].len() > 1)
+            })
+            .collect()
+    }
+
+    /// Returns all other bricks which fall if all the provided bricks are disintegrated at once,
+    /// sorted by [BrickID].
+    pub fn falling_after_removing(&self, removed: &[BrickID]) -> Vec<BrickID> {
+        let mut remaining: Vec<usize> = self.supported_by.iter().map(|x| x.len()).collect();
+        let mut gone = vec![false; self.len()];
+        let mut queue: Vec<BrickID> = Vec::with_capacity(removed.len());
+        for &id in removed {
+            if !gone[id as usize] {
+                gone[id as usize] = true;
+                queue.push(id);
+            }
+        }
+
+        let mut result = Vec::default();
+        while let Some(id) = queue.pop() {
+            for &above in &self.supports[id as usize] {
+                remaining[above as usize] -= 1;
+                if remaining[above as usize] == 0 && !gone[above as usize] {
+                    gone[above as usize] = true;
+                    queue.push(above);
+                    result.push(above);
+                }
+            }
+        }
+
+        result.sort();
+        result
+    }
+
+    /// Returns brick ids ordered so that every brick comes after all bricks it rests on.
+    pub fn topological_order(&self) -> Vec<BrickID> {
+        let mut remaining: Vec<usize> = self.supported_by.iter().map(|x| x.len()).collect();
+        let mut order: Vec<BrickID> = self
+            .id_range()
+            .filter(|&id| remaining[id as usize] == 0)
+            .collect();
+
+        let mut i = 0;
+        while i < order.len() {
+            for &above in &self.supports[order[i] as usize] {
+                remaining[above as usize] -= 1;
+                if remaining[above as usize] == 0 {
+                    order.push(above);
+                }
+            }
+            i += 1;
+        }
+
+        assert_eq!(order.len(), self.len(), "support graph has a cycle");
+        order
+    }
+
+    /// Computes the dominator tree of the support graph, rooted at the ground.
+    ///
+    /// Brick `a` dominates brick `b` if every path of supports from the ground to `b`
+    /// goes through `a` - in other words, `b` falls if `a` is disintegrated.
+    /// As the support graph is acyclic, the immediate dominator of every brick is the
+    /// lowest common ancestor (in the dominator tree) of all bricks it rests on,
+    /// which is found with binary lifting.
+    pub fn dominators(&self) -> Dominators {
+        let n = self.len();
+        let ground = n; // virtual node below all the bricks
+        let levels = (usize::BITS - n.leading_zeros()) as usize + 1;
+
+        // ancestors[k][v] is the 2^k-th ancestor of v in the dominator tree
+        let mut ancestors = vec![vec![ground; n + 1]; levels];
+        let mut depth = vec![0_usize; n + 1];
+
+        let lca = |ancestors: &[Vec<usize>], depth: &[usize], mut a: usize, mut b: usize| {
+            if depth[a] < depth[b] {
+                std::mem::swap(&mut a, &mut b);
+            }
+            for k in (0..levels).rev() {
+                if depth[a] - depth[b] >= 1 << k {
+                    a = ancestors[k][a];
+                }
+            }
+            if a == b {
+                return a;
+            }
+            for k in (0..levels).rev() {
+                if ancestors[k][a] != ancestors[k][b] {
+                    a = ancestors[k][a];
+                    b = ancestors[k][b];
+                }
+            }
+            ancestors[0][a]
+        };
+
+        let order = self.topological_order();
+        let mut immediate = vec![None; n];
+        for &id in &order {
+            let v = id as usize;
+            let idom = self.supported_by[v]
+                .iter()
+                .map(|&below| below as usize)
+                .reduce(|a, b| lca(&ancestors, &depth, a, b))
+                .unwrap_or(ground);
+
+            depth[v] = depth[idom] + 1;
+            ancestors[0][v] = idom;
+            for k in 1..levels {
+                ancestors[k][v] = ancestors[k - 1][ancestors[k - 1][v]];
+            }
+            if idom != ground {
+                immediate[v] = Some(idom as BrickID);
+            }
+        }
+
+        let mut children = vec![Vec::default(); n];
+        let mut subtree_size = vec![1_usize; n];
+        for &id in order.iter().rev() {
+            if let Some(idom) = immediate[id as usize] {
+                children[idom as usize].push(id);
+                subtree_size[idom as usize] += subtree_size[id as usize];
+            }
+        }
+        for c in &mut children {
+            c.sort();
+        }
+
+        Dominators {
+            immediate,
+            children,
+            subtree_size,
+        }
+    }
+}
+
+/// Dominator tree of a [SupportGraph], see [SupportGraph::dominators].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dominators {
+    /// Immediate dominator of every brick, [None] if the brick is only dominated by the ground.
+    pub immediate: Vec<Option<BrickID>>,
+
+    /// Bricks immediately dominated by every brick, sorted by [BrickID].
+    pub children: Vec<Vec<BrickID>>,
+
+    subtree_size: Vec<usize>,
+}
+
+impl Dominators {
+    /// Returns the number of other bricks which fall if the provided brick is disintegrated.
+    pub fn fall_count(&self, id: BrickID) -> usize {
+        self.subtree_size[id as usize] - 1
+    }
+
+    /// Returns the sum of [Dominators::fall_count] over all bricks.
+    pub fn total_fall_count(&self) -> usize {
+        self.subtree_size.iter().map(|x| x - 1).sum()
+    }
+
+    /// Returns the brick whose disintegration causes the most other bricks to fall,
+    /// together with the number of falling bricks. Ties are resolved by the lowest [BrickID].
+    pub fn most_critical(&self) -> Option<(BrickID, usize)> {
+        (0..self.subtree_size.len() as BrickID)
+            .map(|id| (id, self.fall_count(id)))
+            .rev()
+            .max_by_key(|&(_, count)| count)
+    }
+
+    /// Returns all other bricks which fall if the provided brick is disintegrated,
+    /// sorted by [BrickID].
+    pub fn falling(&self, id: BrickID) -> Vec<BrickID> {
+        let mut result = Vec::with_capacity(self.fall_count(id));
+        let mut queue = self.children[id as usize].clone();
+        while let Some(id) = queue.pop() {
+            result.push(id);
+            queue.extend_from_slice(&self.children[id as usize]);
+        }
+        result.sort();
+        result
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrickError {
+    /// The brick with the given id is not above the floor.
+    BelowFloor { id: BrickID, floor: Coord },
+}
+
+impl fmt::Display for BrickError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrickError::BelowFloor { id, floor } => {
+                write!(f, "brick {id} is not above the floor at z = {floor}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BrickError {}
+
+#[derive(Debug, Clone, Default)]
 pub struct Bricks {
     by_id: Vec<Brick>,
-    by_cube: HashMap<Cube, BrickID>,
+    floor: Coord,
 }
 
 impl Bricks {
+    /// Creates a snapshot of bricks above the floor at z = 0.
+    pub fn new(by_id: Vec<Brick>) -> Result<Self, BrickError> {
+        Self { by_id, floor: 0 }.with_floor(0)
+    }
+
+    /// Sets the level of the floor. Returns an error if any brick is not above it.
+    pub fn with_floor(mut self, floor: Coord) -> Result<Self, BrickError> {
+        if let Some(id) = self.by_id.iter().position(|b| b.bottom() <= floor) {
+            return Err(BrickError::BelowFloor {
+                id: id as BrickID,
+                floor,
+            });
+        }
+        self.floor = floor;
+        Ok(self)
+    }
+
+    pub fn floor(&self) -> Coord {
+        self.floor
+    }
+
+    pub fn parse(text: &str) -> Result<Self, BrickError> {
+        Self::new(
+            text.lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(Brick::parse)
+                .collect(),
+        )
+    }
+
+    pub fn id_range(&self) -> Range<BrickID> {
+        0..(self.by_id.len() as BrickID)
+    }
+
+    pub fn get(&self, id: BrickID) -> Brick {
+        self.by_id[id as usize]
+    }
+
+    pub fn bricks(&self) -> &[Brick] {
+        &self.by_id
+    }
+
+    /// Lets all the bricks fall down until they rest on the floor or on other bricks,
+    /// and returns the graph describing which bricks ended up resting on which.
+    ///
+    /// The bricks are dropped in order of their bottom level, each in a single step,
+    /// using a height map storing the highest settled brick over every (x, y) cell.
+    pub fn settle(&mut self) -> SupportGraph {
+        // (top z, top brick) for every (x, y) cell with a settled brick;
+        // the coordinates can be arbitrarily sparse, so a HashMap is used instead of a grid.
+        let mut height_map: HashMap<(Coord, Coord), (Coord, BrickID)> = HashMap::default();
+
+        let mut order: Vec<BrickID> = self.id_range().collect();
+        order.sort_by_key(|&id| self.by_id[id as usize].bottom());
+
+        let mut supported_by = vec![Vec::default(); self.by_id.len()];
+        for id in order {
+            let brick = self.by_id[id as usize];
+
+            let rest_on = brick
+                .footprint()
+                .filter_map(|cell| height_map.get(&cell))
+                .map(|&(z, _)| z)
+                .max()
+                .unwrap_or(self.floor);
+            supported_by[id as usize] = brick
+                .footprint()
+                .filter_map(|cell| match height_map.get(&cell) {
+                    Some(&(z, below)) if z == rest_on => Some(below),
+                    _ => None,
+                })
+                .collect();
+
+            let settled = brick.with_bottom(rest_on + 1);
+            for cell in brick.footprint() {
+                height_map.insert(cell, (settled.top(), id));
+            }
+            self.by_id[id as usize] = settled;
+        }
+
+        SupportGraph::from_supported_by(supported_by)
+    }
+
+    /// Writes all the bricks as boxes in the Wavefront OBJ format, with every brick
+    /// colored by its [BrickID] using the widespread `v x y z r g b` vertex color extension.
+    /// The puzzle's z axis (height) is mapped to the OBJ y axis, as most viewers expect.
+    pub fn write_obj<W: Write>(&self, mut w: W) -> std::io::Result<()> {
+        // Corner i of a brick box is at the end (instead of the start) of the x, y and z axes
+        // if the bits 0, 1 and 2 of i are set, respectively.
+        // Faces are listed counter-clockwise when seen from the outside.
+        const FACES: [[usize; 4]; 6] = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+
+        for (id, brick) in self.by_id.iter().enumerate() {
+            let [r, g, b] = brick_color(id as BrickID);
+            let lo = [brick.start.0, brick.start.1, brick.start.2];
+            let hi = [brick.end.0 + 1, brick.end.1 + 1, brick.end.2 + 1];
+            let corner = |i: usize, axis: usize| {
+                if i & (1 << axis) != 0 {
+                    hi[axis] as i64
+                } else {
+                    lo[axis] as i64
+                }
+            };
+
+            writeln!(w, "o brick_{id}")?;
+            for i in 0..8 {
+                let (x, y, z) = (corner(i, 0), corner(i, 1), corner(i, 2));
+                writeln!(w, "v {x} {z} {} {r:.3} {g:.3} {b:.3}", -y)?;
+            }
+
+            let base = 8 * id + 1;
+            for [a, b, c, d] in FACES {
+                writeln!(w, "f {} {} {} {}", base + a, base + b, base + c, base + d)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Picks a distinct-looking color for a brick by spreading hues with the golden ratio.
+fn brick_color(id: BrickID) -> [f32; 3] {
+    let hue = (id as f32 * 0.618_034).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let [r, g, b] = match hue as u32 {
+        0 => [1.0, x, 0.0],
+        1 => [x, 1.0, 0.0],
+        2 => [0.0, 1.0, x],
+        3 => [0.0, x, 1.0],
+        4 => [x, 0.0, 1.0],
+        _ => [1.0, 0.0, x],
+    };
+
+    // Slightly darken the colors, so that the bright ones remain readable
+    [r * 0.85, g * 0.85, b * 0.85]
+}
+
+pub fn load_input() -> Result<Bricks, BrickError> {
+    let mut text = String::default();
+    stdin()
+        .read_to_string(&mut text)
+        .expect("failed to read from stdin");
+    Bricks::parse(&text)
+}
+
+/// Generates a snapshot of `count` non-overlapping bricks, no longer than 4 cubes,
+/// in a `size` x `size` column above `floor`, for benchmarks and tests.
+pub fn generate_snapshot(count: usize, size: Coord, floor: Coord, seed: u64) -> String {
+    let mut rng = XorShift::new(seed);
+    let mut next = |n: Coord| rng.below(n as u64) as Coord;
+
+    let mut occupied: HashSet<Cube> = HashSet::default();
+    let mut text = String::default();
+    let mut generated = 0;
+    while generated < count {
+        let start = Cube(next(size), next(size), floor + next(4 * count as Coord) + 1);
+        let len = next(4);
+        let end = match next(3) {
+            0 => Cube((start.0 + len).min(size - 1), start.1, start.2),
+            1 => Cube(start.0, (start.1 + len).min(size - 1), start.2),
+            _ => Cube(start.0, start.1, start.2 + len),
+        };
+
+        let cubes = Cube::range(start, end);
+        if cubes.iter().any(|c| occupied.contains(c)) {
+            continue;
+        }
+
+        occupied.extend(cubes);
+        text.push_str(&format!(
+            "{},{},{}~{},{},{}\n",
+            start.0, start.1, start.2, end.0, end.1, end.2
+        ));
+        generated += 1;
+    }
+    text
+}
+
+/// The original chain reaction simulation, repeatedly marking bricks whose supports
+/// have all fallen until nothing changes. Returns all other bricks which fall if
+/// the provided bricks are disintegrated at once, sorted by [BrickID]. [SupportGraph::dominators]
+/// and [SupportGraph::falling_after_removing] should be preferred, this implementation
+/// is only kept to verify and benchmark the faster ones.
+pub fn reference_chain_reaction(supports: &SupportGraph, removed: &[BrickID]) -> Vec<BrickID> {
+    let mut fallen: HashSet<BrickID> = removed.iter().copied().collect();
+    let mut last_fallen_len = 0;
+
+    while fallen.len() != last_fallen_len {
+        last_fallen_len = fallen.len();
+        for (brick_id, foundations) in supports.id_range().zip(&supports.supported_by) {
+            if !foundations.is_empty() && foundations.iter().all(|f| fallen.contains(f)) {
+                fallen.insert(brick_id);
+            }
+        }
+    }
+
+    for id in removed {
+        fallen.remove(id);
+    }
+    let mut result: Vec<BrickID> = fallen.into_iter().collect();
+    result.sort();
+    result
+}
+
+#[derive(Debug)]
+struct CubeBrick(Vec<Cube>);
+
+/// The original, slow brick settling simulation, moving every brick down
+/// one unit at a time and storing every cube in a HashMap.
+/// Kept as a reference implementation to verify [Bricks::settle].
+#[derive(Debug)]
+pub struct ReferenceBricks {
+    by_id: Vec<CubeBrick>,
+    by_cube: HashMap<Cube, BrickID>,
+    floor: Coord,
+}
+
+impl ReferenceBricks {
+    pub fn parse(text: &str) -> Self {
+        let mut by_id = Vec::default();
+        let mut by_cube = HashMap::default();
+
+        for (idx, line) in text.lines().filter(|l| !l.trim().is_empty()).enumerate() {
+            let cubes = Cube::parse_range(line);
+
+            for cube in &cubes {
+                by_cube.insert(*cube, idx as BrickID);
+            }
+
+            by_id.push(CubeBrick(cubes));
+        }
+
+        Self {
+            by_id,
+            by_cube,
+            floor: 0,
+        }
+    }
+
+    /// Sets the level of the floor; all bricks must be above it. Defaults to 0.
+    pub fn with_floor(mut self, floor: Coord) -> Self {
+        self.floor = floor;
+        self
+    }
+
     pub fn id_range(&self) -> Range<BrickID> {
         0..(self.by_id.len() as BrickID)
     }
@@ -62,7 +603,7 @@ impl Bricks {
             let moved = Cube(cube.0, cube.1, cube.2 - 1);
 
             // Can't move into the floor
-            if moved.2 == 0 {
+            if moved.2 == self.floor {
                 return false;
             }
 
@@ -128,42 +669,192 @@ impl Bricks {
         foundations
     }
 
-    pub fn safe_to_disintegrate(&self) -> HashSet<BrickID> {
-        let mut safe_to_disintegrate: HashSet<BrickID> = (0..self.by_id.len() as BrickID).collect();
-
-        for id in 0..self.by_id.len() {
-            let foundations = self.foundations_of(id);
-            if foundations.len() == 1 {
-                for foundation_brick_id in foundations {
-                    safe_to_disintegrate.remove(&foundation_brick_id);
-                }
-            }
-        }
-
-        safe_to_disintegrate
-    }
-
     pub fn foundations(&self) -> HashMap<BrickID, HashSet<BrickID>> {
         self.id_range()
             .map(|i| (i, self.foundations_of(i as usize)))
             .collect()
     }
+
+    pub fn support_graph(&self) -> SupportGraph {
+        SupportGraph::from_supported_by(
+            (0..self.by_id.len())
+                .map(|id| self.foundations_of(id).into_iter().collect())
+                .collect(),
+        )
+    }
 }
 
-pub fn load_input() -> Bricks {
-    let mut by_id = Vec::default();
-    let mut by_cube = HashMap::default();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "1,0,1~1,2,1
+0,0,2~2,0,2
+0,2,3~2,2,3
+0,0,4~0,2,4
+2,0,5~2,2,5
+0,1,6~2,1,6
+1,1,8~1,1,9
+";
 
-    for (idx, line) in stdin().lines().enumerate() {
-        let line = line.expect("failed to read from stdin");
-        let cubes = Cube::parse_range(&line);
+    fn check_equivalent(snapshot: &str, floor: Coord) {
+        let mut reference = ReferenceBricks::parse(snapshot).with_floor(floor);
+        reference.all_down();
 
-        for cube in &cubes {
-            by_cube.insert(*cube, idx as BrickID);
+        let mut bricks = Bricks::parse(snapshot)
+            .and_then(|b| b.with_floor(floor))
+            .unwrap();
+        let supports = bricks.settle();
+
+        assert_eq!(bricks.bricks().len(), reference.by_id.len());
+        for (brick, reference_brick) in bricks.bricks().iter().zip(&reference.by_id) {
+            assert_eq!(Cube::range(brick.start, brick.end), reference_brick.0);
         }
 
-        by_id.push(Brick(cubes));
+        let expected = reference.support_graph();
+        assert_eq!(supports.supported_by, expected.supported_by);
+        assert_eq!(supports.supports, expected.supports);
     }
 
-    Bricks { by_id, by_cube }
+    /// Compares the chain reactions from the dominator tree with [reference_chain_reaction].
+    fn check_dominators(supports: &SupportGraph) {
+        let dominators = supports.dominators();
+        let mut total = 0;
+        for id in supports.id_range() {
+            let expected = reference_chain_reaction(supports, &[id]);
+            assert_eq!(dominators.falling(id), expected, "brick {id}");
+            assert_eq!(dominators.fall_count(id), expected.len(), "brick {id}");
+            total += expected.len();
+        }
+        assert_eq!(dominators.total_fall_count(), total);
+    }
+
+    #[test]
+    fn settle_sample() {
+        check_equivalent(SAMPLE, 0);
+
+        let mut bricks = Bricks::parse(SAMPLE).unwrap();
+        let supports = bricks.settle();
+        assert_eq!(supports.safe_to_disintegrate().len(), 5);
+        assert_eq!(supports.dominators().total_fall_count(), 7);
+    }
+
+    #[test]
+    fn settle_generated() {
+        check_equivalent(&generate_snapshot(200, 10, 0, 0x2023_1222), 0);
+        check_equivalent(&generate_snapshot(300, 4, 0, 42), 0);
+        check_equivalent(&generate_snapshot(100, 3, 100_000, 7), 100_000);
+    }
+
+    #[test]
+    fn dominators_sample() {
+        let supports = Bricks::parse(SAMPLE).unwrap().settle();
+        let dominators = supports.dominators();
+        assert_eq!(dominators.falling(0), vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(dominators.falling(5), vec![6]);
+        assert!(dominators.falling(1).is_empty());
+        check_dominators(&supports);
+    }
+
+    #[test]
+    fn dominators_generated() {
+        for (count, size, seed) in [(200, 10, 0x2023_1222), (300, 4, 42), (150, 3, 7)] {
+            let supports = Bricks::parse(&generate_snapshot(count, size, 0, seed))
+                .unwrap()
+                .settle();
+            check_dominators(&supports);
+        }
+    }
+
+    #[test]
+    fn falling_after_removing() {
+        let supports = Bricks::parse(SAMPLE).unwrap().settle();
+        assert_eq!(supports.falling_after_removing(&[1, 2]), vec![3, 4, 5, 6]);
+        assert_eq!(supports.falling_after_removing(&[3, 4]), vec![5, 6]);
+        assert_eq!(
+            supports.falling_after_removing(&[6, 6]),
+            Vec::<BrickID>::default()
+        );
+
+        let supports = Bricks::parse(&generate_snapshot(200, 5, 0, 99))
+            .unwrap()
+            .settle();
+        let ids: Vec<BrickID> = supports.id_range().collect();
+        for size in 1..=4 {
+            for removed in ids.windows(size).step_by(3) {
+                assert_eq!(
+                    supports.falling_after_removing(removed),
+                    reference_chain_reaction(&supports, removed),
+                    "removing {removed:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn most_critical() {
+        let supports = Bricks::parse(SAMPLE).unwrap().settle();
+        assert_eq!(supports.dominators().most_critical(), Some((0, 6)));
+        assert_eq!(SupportGraph::default().dominators().most_critical(), None);
+
+        // Both bricks on the floor hold one brick each - the lower id wins the tie
+        let supports = Bricks::parse("0,0,1~0,0,1\n1,0,1~1,0,1\n1,0,2~1,0,2\n0,0,2~0,0,2\n")
+            .unwrap()
+            .settle();
+        assert_eq!(supports.dominators().most_critical(), Some((0, 1)));
+    }
+
+    #[test]
+    fn write_obj() {
+        let mut bricks = Bricks::parse(SAMPLE).unwrap();
+        bricks.settle();
+
+        let mut obj = Vec::default();
+        bricks.write_obj(&mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+
+        let count = |prefix: &str| obj.lines().filter(|l| l.starts_with(prefix)).count();
+        assert_eq!(count("o "), 7);
+        assert_eq!(count("v "), 7 * 8);
+        assert_eq!(count("f "), 7 * 6);
+
+        // Every face refers to 4 distinct vertices of its own brick
+        for (i, face) in obj.lines().filter(|l| l.starts_with("f ")).enumerate() {
+            let brick = i / 6;
+            let mut vertices: Vec<usize> =
+                face[2..].split(' ').map(|v| v.parse().unwrap()).collect();
+            vertices.sort();
+            vertices.dedup();
+            assert_eq!(vertices.len(), 4);
+            assert!(vertices
+                .iter()
+                .all(|v| (8 * brick + 1..=8 * brick + 8).contains(v)));
+        }
+
+        // The first brick rests at z = 1, which is mapped to the OBJ y axis
+        let first_vertex = obj.lines().find(|l| l.starts_with("v ")).unwrap();
+        assert!(first_vertex.starts_with("v 1 1 0 "), "{first_vertex}");
+    }
+
+    #[test]
+    fn settle_sparse() {
+        let mut bricks =
+            Bricks::parse("0,0,5~0,0,6\n4000000000,0,3~4000000000,2,3\n0,0,9~0,0,9\n").unwrap();
+        let supports = bricks.settle();
+        assert_eq!(supports.supported_by, vec![vec![], vec![], vec![0]]);
+        assert_eq!(bricks.get(1).bottom(), 1);
+        assert_eq!(bricks.get(2).bottom(), 3);
+    }
+
+    #[test]
+    fn bricks_below_floor() {
+        assert_eq!(
+            Bricks::parse("0,0,0~0,0,1\n").unwrap_err(),
+            BrickError::BelowFloor { id: 0, floor: 0 }
+        );
+        assert_eq!(
+            Bricks::parse(SAMPLE).unwrap().with_floor(3).unwrap_err(),
+            BrickError::BelowFloor { id: 0, floor: 3 }
+        );
+    }
 }
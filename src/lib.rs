@@ -1,4 +1,5 @@
 pub mod bitset;
+pub mod rng;
 
 pub mod day02;
 pub mod day03;
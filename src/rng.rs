@@ -0,0 +1,28 @@
+// Copyright (c) 2023 Mikołaj Kuranowski
+// SPDX-License-Identifier: MIT
+
+/// XorShift is the xorshift64 pseudo-random number generator. It is good enough
+/// to scatter things around in generated benchmark and test inputs, but nothing more.
+#[derive(Debug, Clone)]
+pub struct XorShift(u64);
+
+impl XorShift {
+    /// Creates a generator with the provided seed, which must not be zero
+    /// (the generator would only return zeros).
+    pub fn new(seed: u64) -> Self {
+        assert_ne!(seed, 0, "xorshift seed must not be zero");
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number from `0..n`. Panics if `n` is zero.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}